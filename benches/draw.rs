@@ -0,0 +1,84 @@
+//! Measures how long drawing a frame takes at several thread counts.
+//!
+//! The moving camera preview is drawn both the way `Image::draw` does it, every thread writing its own
+//! rows of the frame, and the way it used to, locking a `Mutex` around the whole frame for every pixel.
+//! Progressive refinement of a still camera already wrote rows before, so only `Image::draw` is timed.
+//!
+//! Run with `cargo bench --bench draw`.
+
+use std::{sync::Mutex, thread, time::{Duration, Instant}};
+
+use rayon::prelude::*;
+use ray_tracing_in_a_weekend::image::Image;
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 360;
+const FRAMES: u32 = 20;
+/// The preview traces every third pixel of every third row.
+const STEPS: usize = 3;
+
+fn time_frames(mut draw: impl FnMut()) -> Duration {
+    // One frame to warm up the thread pool and caches.
+    draw();
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        draw();
+    }
+    start.elapsed() / FRAMES
+}
+
+/// The preview as it was drawn before, with a lock taken for every pixel written.
+fn draw_preview_locked(image: &Image, frame: &mut [u8]) {
+    let frame = Mutex::new(frame);
+    (0..WIDTH).into_par_iter().step_by(STEPS).for_each(|i| {
+        (0..HEIGHT).into_par_iter().step_by(STEPS).for_each(|j| {
+            let index = (i + WIDTH * (HEIGHT - j - 1)) as usize * 4;
+            let color = image.preview_pixel(i, j);
+
+            let mut frame = frame.lock().unwrap();
+            frame[index..index + 3].copy_from_slice(&color);
+            frame[index + 3] = 255;
+        });
+    });
+}
+
+/// The preview as `Image::draw` draws it, every row of the frame a disjoint slice.
+fn draw_preview_rows(image: &Image, frame: &mut [u8]) {
+    frame.par_chunks_mut(WIDTH as usize * 4).enumerate().for_each(|(row, pixels)| {
+        let j = HEIGHT - row as u32 - 1;
+        if !(j as usize).is_multiple_of(STEPS) {
+            return;
+        }
+        for (i, pixel) in pixels.chunks_mut(4).enumerate().step_by(STEPS) {
+            pixel[..3].copy_from_slice(&image.preview_pixel(i as u32, j));
+            pixel[3] = 255;
+        }
+    });
+}
+
+fn main() {
+    let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(1);
+    let mut threads = vec![1, 2, 4, cores];
+    threads.sort_unstable();
+    threads.dedup();
+
+    println!("{} cores, {}x{}, average of {} frames", cores, WIDTH, HEIGHT, FRAMES);
+    println!("{:>7} {:>16} {:>16} {:>16}", "threads", "preview, mutex", "preview, rows", "progressive");
+    let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+
+    for count in threads {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(count).build().unwrap();
+        let (locked, rows, progressive) = pool.install(|| {
+            let mut image = Image::new(WIDTH, HEIGHT, 1, 20);
+            image.set_refine(false);
+            let locked = time_frames(|| draw_preview_locked(&image, &mut frame));
+            let rows = time_frames(|| draw_preview_rows(&image, &mut frame));
+
+            image.set_refine(true);
+            let progressive = time_frames(|| image.draw(&mut frame));
+            (locked, rows, progressive)
+        });
+        println!("{:>7} {:>16.2?} {:>16.2?} {:>16.2?}", count, locked, rows, progressive);
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use ray_tracing_in_a_weekend::{output::{exr::ExrPixelType, tone_map::ToneMapper}, samplers::sampler::SamplerType};
+
+use crate::{SAMPLES_PER_PIXEL, MAX_DEPTH};
+
+pub fn usage() -> String {
+    format!("\
+Usage:
+    ray_tracing_in_a_weekend [SCENE]
+        Opens the interactive viewer, rendering SCENE instead of the random world when given.
+
+    ray_tracing_in_a_weekend render [OPTIONS] [SCENE]
+        Renders a single image without opening a window and writes it to disk.
+
+SCENE is either a TOML scene description (.toml) or a Wavefront OBJ file (.obj).
+
+Render options, these override the settings of the scene description:
+    -o, --output <FILE>     Image file to write, the format follows the extension [default: image.png]
+                            .exr and .hdr keep the linear radiance, anything else gets tone mapped
+    -w, --width <PIXELS>    Width of the image, at least 2 [default: 800]
+    -h, --height <PIXELS>   Height of the image, at least 2 [default: 450]
+    -s, --samples <COUNT>   Samples per pixel [default: {}]
+    -d, --depth <COUNT>     Maximum number of bounces per ray [default: {}]
+        --seed <NUMBER>     Seed of the sampling, the same seed gives the same image [default: 0]
+        --sampler <NAME>    How samples are spread: random, stratified, halton or sobol [default: sobol]
+        --tone-map <NAME>   How radiance maps to colors: clamp, reinhard, aces or filmic [default: clamp]
+        --exr-type <TYPE>   Precision of EXR channels: half or float [default: half]
+        --denoise           Denoise the render, guided by its albedo, normals and depth
+        --no-denoise        Keep the render noisy even when the scene asks for denoising
+        --aovs              Also write albedo, normal, position, depth, object and material ids, as
+                            layers of an .exr output or as separate .exr files next to any other output
+        --help              Prints this message", SAMPLES_PER_PIXEL, MAX_DEPTH)
+}
+
+pub enum Command {
+    View { scene: Option<String> },
+    Render(RenderOptions),
+    Help,
+}
+
+pub struct RenderOptions {
+    pub output: PathBuf,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples: Option<u32>,
+    pub depth: Option<u32>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerType>,
+    pub tone_mapper: Option<ToneMapper>,
+    pub exr_type: ExrPixelType,
+    pub denoise: Option<bool>,
+    pub aovs: bool,
+    pub scene: Option<String>,
+}
+
+/// Parses the command line arguments, without the program name.
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let first = match args.next() {
+        Some(first) => first,
+        None => return Ok(Command::View { scene: None }),
+    };
+
+    match first.as_str() {
+        "--help" => Ok(Command::Help),
+        "render" => parse_render(args),
+        _ => match args.next() {
+            Some(extra) => Err(format!("unexpected argument '{}'", extra)),
+            None => Ok(Command::View { scene: Some(first) }),
+        }
+    }
+}
+
+fn parse_render(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut options = RenderOptions {
+        output: PathBuf::from("image.png"),
+        width: None,
+        height: None,
+        samples: None,
+        depth: None,
+        seed: None,
+        sampler: None,
+        tone_mapper: None,
+        exr_type: ExrPixelType::default(),
+        denoise: None,
+        aovs: false,
+        scene: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("'{}' needs a value", name));
+        match arg.as_str() {
+            "-o" | "--output" => options.output = PathBuf::from(value(&arg)?),
+            "-w" | "--width" => options.width = Some(parse_dimension(&arg, &value(&arg)?)?),
+            "-h" | "--height" => options.height = Some(parse_dimension(&arg, &value(&arg)?)?),
+            "-s" | "--samples" => options.samples = Some(parse_positive(&arg, &value(&arg)?)?),
+            "-d" | "--depth" => options.depth = Some(parse_positive(&arg, &value(&arg)?)?),
+            "--seed" => options.seed = Some(parse_seed(&value(&arg)?)?),
+            "--sampler" => options.sampler = Some(parse_sampler(&value(&arg)?)?),
+            "--tone-map" => options.tone_mapper = Some(parse_tone_mapper(&value(&arg)?)?),
+            "--exr-type" => {
+                let name = value(&arg)?;
+                options.exr_type = ExrPixelType::from_name(&name)
+                    .ok_or_else(|| format!("unknown EXR type '{}', expected 'half' or 'float'", name))?;
+            },
+            "--denoise" => options.denoise = Some(true),
+            "--no-denoise" => options.denoise = Some(false),
+            "--aovs" => options.aovs = true,
+            "--help" => return Ok(Command::Help),
+            other if other.starts_with('-') => return Err(format!("unknown option '{}'", other)),
+            _ if options.scene.is_none() => options.scene = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    Ok(Command::Render(options))
+}
+
+fn parse_seed(value: &str) -> Result<u64, String> {
+    value.parse::<u64>().map_err(|_| format!("'--seed' expects a whole number, got '{}'", value))
+}
+
+fn parse_sampler(value: &str) -> Result<SamplerType, String> {
+    SamplerType::from_name(value).ok_or_else(|| {
+        format!("unknown sampler '{}', expected one of 'random', 'stratified', 'halton' or 'sobol'", value)
+    })
+}
+
+fn parse_tone_mapper(value: &str) -> Result<ToneMapper, String> {
+    ToneMapper::from_name(value).ok_or_else(|| {
+        format!("unknown tone mapping '{}', expected one of 'clamp', 'reinhard', 'aces' or 'filmic'", value)
+    })
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("'{}' expects a positive number, got '{}'", name, value)),
+    }
+}
+
+/// Image sizes need at least two pixels along each axis, the camera spreads its rays over `width - 1` steps.
+fn parse_dimension(name: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(number) if number >= 2 => Ok(number),
+        _ => Err(format!("'{}' expects at least 2 pixels, got '{}'", name, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_args(args: &[&str]) -> Result<Command, String> {
+        parse(std::iter::once("render").chain(args.iter().copied()).map(String::from))
+    }
+
+    #[test]
+    fn test_image_needs_two_pixels_per_axis() {
+        assert_eq!(render_args(&["--width", "1"]).err().unwrap(), "'--width' expects at least 2 pixels, got '1'");
+        assert_eq!(render_args(&["-h", "0"]).err().unwrap(), "'-h' expects at least 2 pixels, got '0'");
+
+        match render_args(&["--width", "2", "--height", "2"]) {
+            Ok(Command::Render(options)) => assert_eq!((options.width, options.height), (Some(2), Some(2))),
+            _ => panic!("expected render options"),
+        }
+    }
+
+    #[test]
+    fn test_denoise_can_be_turned_off() {
+        let denoise = |args: &[&str]| match render_args(args) {
+            Ok(Command::Render(options)) => options.denoise,
+            _ => panic!("expected render options"),
+        };
+        assert_eq!(denoise(&[]), None);
+        assert_eq!(denoise(&["--denoise"]), Some(true));
+        assert_eq!(denoise(&["--denoise", "--no-denoise"]), Some(false));
+    }
+}
@@ -1,13 +1,25 @@
-use std::{sync::Mutex, f64::INFINITY};
-
 use crate::primitives::{color::Color, vector::{Vector, Vec3}, ray::Ray};
-use crate::shapes::{sphere::Sphere, material::Material, hitrecord::HitRecord};
-use crate::objects::camera::Camera;
+use crate::shapes::{sphere::Sphere, material::Material, hitrecord::HitRecord, hittable::Hittable, hittable_list::HittableList, bvh::Bvh, light::LightList};
+use crate::objects::{camera::{Camera, CameraSettings}, background::Background};
+use crate::loaders::scene::Scene;
+use crate::output::{film::Film, aov::Aov, denoise::Denoiser, tone_map::ToneMapper};
+use crate::samplers::{
+    pcg::Pcg32, sampler::{Sampler, SamplerType}, random::RandomSampler, stratified::StratifiedSampler,
+    halton::HaltonSampler, sobol::SobolSampler
+};
 
 use rayon::prelude::*;
-use rand::{self, Rng};
+use rand::Rng;
 use winit::{event::{DeviceEvent, MouseScrollDelta, ElementState, KeyboardInput, VirtualKeyCode}, dpi::PhysicalPosition};
 
+/// Number of bounces a path takes before Russian roulette may terminate it.
+const ROULETTE_START_DEPTH: u32 = 3;
+
+/// Ids of the mouse buttons in raw device events.
+const LEFT_BUTTON: u32 = 1;
+const MIDDLE_BUTTON: u32 = 2;
+const RIGHT_BUTTON: u32 = 3;
+
 #[derive(Clone, Copy, PartialEq)]
 enum State {
     Panning,
@@ -18,52 +30,87 @@ enum State {
 pub struct Image {
     width: u32,
     height: u32,
-    world: Vec<Sphere>,
+    world: Bvh,
+    lights: LightList,
     camera: Camera,
     max_depth: u32,
     max_samples: u32,
-    fov: f64,
-    look_from: Vector,
-    look_at: Vector,
-    up: Vector,
+    view: CameraSettings,
+    cursor: PhysicalPosition<f64>,
+    /// What rays that miss everything see.
+    background: Background,
     state: State,
     steps: usize,
-    full_rendered: bool,
-    render_crisp: bool,
+    /// Every sample taken for each pixel since the camera last moved.
+    film: Film,
+    /// Whether samples keep accumulating while the camera is still.
+    refine: bool,
+    /// Every sample of every pixel draws its random numbers from a generator seeded with this.
+    seed: u64,
+    sampler: SamplerType,
+    tone_mapper: ToneMapper,
+    /// Whether the preview, the accumulated frames and renders get denoised.
+    denoise: bool,
+    denoiser: Denoiser,
+    /// Whether the film changed since it was last drawn, while refinement is paused.
+    redraw: bool,
 }
 
 impl Image {
     pub fn new(width: u32, height: u32, max_samples: u32, max_depth: u32) -> Image {
-        let aspect_ratio = width as f64 / height as f64;
-        let world = random_world();
-        let fov = 60.0;
-        let look_from = Vector::new(0.0, 2.0, 4.0);
-        let look_at = Vector::new(0.0, 0.0, 0.0);
-        let up = Vector::new(0.0, 1.0, 0.0);
-
-        let camera = Camera::from_ratio(
-            aspect_ratio, 
-            fov,
-            look_from,
-            look_at,
-            up
+        Image::with_world(width, height, max_samples, max_depth, random_world(&mut Pcg32::new(0, 0)))
+    }
+
+    /// Creates an image that renders the given world instead of a randomly generated one.
+    pub fn with_world(width: u32, height: u32, max_samples: u32, max_depth: u32, world: HittableList) -> Image {
+        Image::with_world_and_camera(width, height, max_samples, max_depth, world, CameraSettings::default())
+    }
+
+    /// Creates an image that renders a scene loaded from a scene description file.
+    pub fn from_scene(scene: Scene) -> Image {
+        let settings = scene.render;
+        let mut image = Image::with_world_and_camera(
+            settings.width, settings.height, settings.samples, settings.max_depth, scene.world, scene.camera
         );
+        image.background = scene.background;
+        image.film = image.new_film(settings.width, settings.height);
+        image.seed = settings.seed;
+        image.sampler = settings.sampler;
+        image.tone_mapper = settings.tone_mapper;
+        image.denoise = settings.denoise;
+        image
+    }
+
+    pub fn with_world_and_camera(
+        width: u32, height: u32, max_samples: u32, max_depth: u32, world: HittableList, camera: CameraSettings
+    ) -> Image {
+        let aspect_ratio = width as f64 / height as f64;
+        let lights = LightList::new(world.lights());
+        let world = Bvh::new(world);
+        let view = camera;
+        let camera = Camera::from_settings(aspect_ratio, &view);
 
         Image {
             width,
             height,
             world,
+            lights,
             camera,
             max_samples,
             max_depth,
-            fov,
-            look_from,
-            look_at,
-            up,
+            view,
+            cursor: PhysicalPosition::new(0.0, 0.0),
+            background: Background::default(),
             state: State::Static,
             steps: 3,
-            full_rendered: false,
-            render_crisp: false
+            film: Film::new(width, height),
+            refine: true,
+            seed: 0,
+            sampler: SamplerType::default(),
+            tone_mapper: ToneMapper::default(),
+            denoise: false,
+            denoiser: Denoiser::default(),
+            redraw: false
         }
     }
 
@@ -71,40 +118,108 @@ impl Image {
         if new_size.width > 0 && new_size.height > 0 {
             self.width = new_size.width;
             self.height = new_size.height;
-            self.camera = Camera::from_ratio(
-                self.width as f64 / self.height as f64, 
-                self.fov, 
-                self.look_from, 
-                self.look_at, 
-                self.up
-            );
+            self.camera = Camera::from_settings(self.width as f64 / self.height as f64, &self.view);
+            self.film = self.new_film(self.width, self.height);
         }
     }
 
+    /// An empty film, see-through where nothing gets hit when the background is transparent.
+    fn new_film(&self, width: u32, height: u32) -> Film {
+        Film::new(width, height).with_transparency(self.background.is_transparent())
+    }
+
+    /// Changes the seed of the sampling, the same seed always gives the same image.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reset_accumulation();
+    }
+
+    /// Changes how the samples of each pixel are spread.
+    pub fn set_sampler(&mut self, sampler: SamplerType) {
+        self.sampler = sampler;
+        self.reset_accumulation();
+    }
+
+    /// Changes how the radiance of the image is mapped to displayable colors.
+    pub fn set_tone_mapper(&mut self, tone_mapper: ToneMapper) {
+        self.tone_mapper = tone_mapper;
+        self.redraw = true;
+    }
+
+    /// Turns denoising of the preview, the accumulated frames and renders on or off.
+    pub fn set_denoise(&mut self, denoise: bool) {
+        self.denoise = denoise;
+        self.redraw = true;
+    }
+
+    /// Keeps track of the cursor inside the window, used to pick the object to focus on.
+    pub fn set_cursor_position(&mut self, position: PhysicalPosition<f64>) {
+        self.cursor = position;
+    }
+
+    /// Moves the focus plane onto the object under the cursor, if there is one.
+    pub fn focus_on_cursor(&mut self) {
+        let u = self.cursor.x / (self.width - 1) as f64;
+        let v = (self.height as f64 - 1.0 - self.cursor.y) / (self.height - 1) as f64;
+        let ray = self.camera.get_pinhole_ray(u, v);
+
+        let mut record = HitRecord::new();
+        if self.world.hit(&ray, 0.001, f64::INFINITY, &mut record) {
+            self.view.focus_distance = self.camera.depth_of(record.point.unwrap());
+            self.camera = Camera::from_settings(self.width as f64 / self.height as f64, &self.view);
+            self.reset_accumulation();
+        }
+    }
+
+    fn change_aperture(&mut self, change: f64) {
+        self.view.aperture = (self.view.aperture + change).max(0.0);
+        self.camera = Camera::from_settings(self.width as f64 / self.height as f64, &self.view);
+        self.reset_accumulation();
+    }
+
     pub fn handle_device(&mut self, event: &DeviceEvent, window_focused: bool) {
         if window_focused {
             match event {
                 DeviceEvent::Key(
                     KeyboardInput {
-                        virtual_keycode: Some(key),
-                        state,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        state: ElementState::Pressed,
                         ..
                     }
-                ) => {
-                    match key {
-                        VirtualKeyCode::Space => {
-                            if *state == ElementState::Pressed {
-                                self.render_crisp = !self.render_crisp;
-                            }
-                        },
-                        _ => {}
+                ) => self.refine = !self.refine,
+                DeviceEvent::Key(
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd),
+                        state: ElementState::Pressed,
+                        ..
                     }
-                },
+                ) => self.change_aperture(0.05),
+                DeviceEvent::Key(
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract),
+                        state: ElementState::Pressed,
+                        ..
+                    }
+                ) => self.change_aperture(-0.05),
+                DeviceEvent::Key(
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::T),
+                        state: ElementState::Pressed,
+                        ..
+                    }
+                ) => self.set_tone_mapper(self.tone_mapper.next()),
+                DeviceEvent::Key(
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::D),
+                        state: ElementState::Pressed,
+                        ..
+                    }
+                ) => self.set_denoise(!self.denoise),
                 DeviceEvent::Button {
                     state, button, 
                 } => {
-                    match button {
-                        3 /* right click */ => {
+                    match *button {
+                        RIGHT_BUTTON => {
                             match state {
                                 ElementState::Pressed => {
                                     self.state = State::Rotating;
@@ -114,7 +229,7 @@ impl Image {
     
                             }
                         },
-                        1 /* left click */ => {
+                        LEFT_BUTTON => {
                             match state {
                                 ElementState::Pressed => {
                                     self.state = State::Panning;
@@ -123,10 +238,13 @@ impl Image {
                                 ElementState::Released => self.state = State::Static,
                             }
                         },
+                        MIDDLE_BUTTON if *state == ElementState::Pressed => {
+                            self.focus_on_cursor();
+                        },
                         _ => {}
                     }
                 },
-                DeviceEvent::MouseMotion {delta: (x_delta, y_delta)} => if self.state != State::Static {
+                DeviceEvent::MouseMotion {delta: (x_delta, y_delta)} => {
                     match self.state {
                         State::Panning => {
                             let x_relative = (*x_delta / self.width as f64 * 3.0).abs();
@@ -140,26 +258,23 @@ impl Image {
                                 }
                             };
 
-                            let look_direction  = self.camera.get_ray((self.width / 2) as f64, (self.height / 2) as f64).direction.cross(&Vector::new(0.0, 1.0, 0.0)).unit_vector().cross(&unit).unit_vector();
-                            let mut look_from = self.look_from + look_direction * x_relative;
-                            let mut look_at = self.look_at + look_direction * x_relative;
+                            let look_direction  = self.camera.get_pinhole_ray((self.width / 2) as f64, (self.height / 2) as f64).direction.cross(&Vector::new(0.0, 1.0, 0.0)).unit_vector().cross(&unit).unit_vector();
+                            let mut look_from = self.view.look_from + look_direction * x_relative;
+                            let mut look_at = self.view.look_at + look_direction * x_relative;
 
-                            look_from.y = look_from.y + y_relative;
-                            look_at.y = look_at.y + y_relative;
+                            look_from.y += y_relative;
+                            look_at.y += y_relative;
 
-                            // let look_at = Vector::new(self.look_at.x() - x_relative, self.look_at.y() + y_relative, self.look_at.z());
-                            // let look_from = Vector::new(self.look_from.x() - x_relative, self.look_from.y() + y_relative, self.look_from.z());
+                            // let look_at = Vector::new(self.view.look_at.x() - x_relative, self.view.look_at.y() + y_relative, self.view.look_at.z());
+                            // let look_from = Vector::new(self.view.look_from.x() - x_relative, self.view.look_from.y() + y_relative, self.view.look_from.z());
                             self.update_position_and_look(look_from, look_at);
-                            self.full_rendered = false;
                         },
                         State::Rotating => {
                             let x_relative = *x_delta / self.width as f64 * 5.0;
                             let y_relative = *y_delta / self.height as f64 * 5.0;
     
-                            let look_at = Vector::new(self.look_at.x() - x_relative, self.look_at.y() + y_relative, self.look_at.z());
-                            self.update_position_and_look(self.look_from, look_at);
-
-                            self.full_rendered = false;
+                            let look_at = Vector::new(self.view.look_at.x() - x_relative, self.view.look_at.y() + y_relative, self.view.look_at.z());
+                            self.update_position_and_look(self.view.look_from, look_at);
                         },
                         _ => {}
                     }
@@ -185,12 +300,11 @@ impl Image {
                         }
                     };
 
-                    let look_direction  = self.camera.get_ray(((self.width - 1)/ 2) as f64, ((self.height - 1) / 2) as f64).direction.cross(&unit).unit_vector();
-                    let look_from = self.look_from + look_direction * (scroll / 120.0).abs();
-                    let look_at = self.look_at + look_direction * (scroll / 120.0).abs();
+                    let look_direction  = self.camera.get_pinhole_ray(((self.width - 1)/ 2) as f64, ((self.height - 1) / 2) as f64).direction.cross(&unit).unit_vector();
+                    let look_from = self.view.look_from + look_direction * (scroll / 120.0).abs();
+                    let look_at = self.view.look_at + look_direction * (scroll / 120.0).abs();
 
                     self.update_position_and_look(look_from, look_at);
-                    self.full_rendered = false;
                 },
                 _ => {}
             }
@@ -200,152 +314,418 @@ impl Image {
     }
 
     pub fn update_position_and_look(&mut self, look_from: Vector, look_at: Vector) {
-        self.look_at = look_at;
-        self.look_from = look_from;
-        self.camera = Camera::from_ratio(
-            self.width as f64 / self.height as f64, 
-            self.fov, 
-            self.look_from, 
-            self.look_at, 
-            self.up
-        );
+        self.view.look_at = look_at;
+        self.view.look_from = look_from;
+        self.camera = Camera::from_settings(self.width as f64 / self.height as f64, &self.view);
+        self.reset_accumulation();
+    }
+
+    /// Turns progressive refinement on or off, when off a still camera keeps showing the quick preview.
+    pub fn set_refine(&mut self, refine: bool) {
+        self.refine = refine;
+        self.reset_accumulation();
+    }
+
+    /// Throws away the accumulated samples, needed whenever the view changes.
+    fn reset_accumulation(&mut self) {
+        if self.film.samples() > 0 {
+            self.film.clear();
+        }
+    }
+
+    /// Whether the next frame adds to the accumulated samples instead of drawing a quick preview.
+    fn accumulating(&self) -> bool {
+        self.state == State::Static && (self.refine || self.film.samples() > 0)
     }
 
     pub fn clear(&mut self, frame: &mut [u8]) {
-        if !self.full_rendered {
-            let _ = frame.into_par_iter().for_each(|pixel| {
+        // The preview skips pixels, clear them so the previous frame does not show through.
+        if !self.accumulating() {
+            frame.into_par_iter().for_each(|pixel| {
                 *pixel = 0;
             });
         }
     }
 
     pub fn draw(&mut self, frame: &mut [u8]) {
-        if !self.full_rendered || !self.render_crisp {
-            let frame = Mutex::new(frame);
-            let samples;
-
-            if self.state == State::Static && self.render_crisp {
-                self.steps = 1;
-                samples = self.max_samples;
-                println!("rendering full quality image...");
-            } else {
-                samples = 1;
-                self.steps = 3;
+        if self.accumulating() {
+            if self.refine {
+                self.accumulate();
+                self.redraw = true;
             }
-            let _ = (0..self.width).into_par_iter().step_by(self.steps).for_each(|i| {
-                let _ = (0..self.height).into_par_iter().step_by(self.steps).for_each(|j| {
-    
-                    let index = (i + self.width * (self.height - j - 1)) as usize * 4;
-                    let color = self.get_pixel_color(i, j, samples).pixels(samples);
-    
-                    let mut frame = frame.lock().unwrap();
-                    frame[index] = color[0];
-                    frame[index + 1] = color[1];
-                    frame[index + 2] = color[2];
-                    frame[index + 3] = 255;
-                });
-            });
-            if self.state == State::Static && self.render_crisp {
-                self.full_rendered = true;
-                println!("complete!");
+            // While paused the frame only changes when the tone mapping or denoising does.
+            if self.redraw {
+                self.draw_film(frame);
+                self.redraw = false;
             }
-            // println!("{:?}", start.elapsed());
+            return;
+        }
+
+        // A low resolution, low depth preview while the camera moves, only every `steps` pixel gets traced.
+        self.reset_accumulation();
+        self.steps = 3;
+        let steps = self.steps;
+        if self.denoise {
+            self.draw_denoised_preview(frame);
+            return;
         }
+
+        // Every row of the frame is a disjoint slice, so threads write their rows without any locking.
+        frame.par_chunks_mut(self.width as usize * 4).enumerate().for_each(|(row, pixels)| {
+            let j = self.height - row as u32 - 1;
+            if !(j as usize).is_multiple_of(steps) {
+                return;
+            }
+
+            for (i, pixel) in pixels.chunks_mut(4).enumerate().step_by(steps) {
+                write_pixel(pixel, self.preview_pixel(i as u32, j));
+            }
+        });
+    }
+
+    /// Tone mapped color of the pixel in column `i` and row `j`, counting rows from the bottom, as the quick
+    /// preview shows it: a single low depth sample.
+    pub fn preview_pixel(&self, i: u32, j: u32) -> [u8; 3] {
+        let color = self.get_pixel_color(i, j, 0, 1, 3, &mut Aov::default());
+        self.tone_mapper.to_srgb8(color)
+    }
+
+    /// Traces the preview pixels into a film of their own, so they can be denoised and scaled up to fill the frame.
+    fn draw_denoised_preview(&self, frame: &mut [u8]) {
+        let steps = self.steps as u32;
+        let width = self.width.div_ceil(steps);
+        let mut preview = self.new_film(width, self.height.div_ceil(steps));
+
+        preview.rows_mut().enumerate().for_each(|(row, (colors, aovs))| {
+            let j = self.height - row as u32 * steps - 1;
+            for (column, (color, aov)) in colors.iter_mut().zip(aovs).enumerate() {
+                *color = self.get_pixel_color(column as u32 * steps, j, 0, 1, 3, aov);
+            }
+        });
+        preview.add_samples(1);
+        let preview = preview.denoised(&self.denoiser).to_rgba8(self.tone_mapper);
+
+        frame.par_chunks_mut(self.width as usize * 4).enumerate().for_each(|(row, pixels)| {
+            let first = row / steps as usize * width as usize;
+            for (i, pixel) in pixels.chunks_mut(4).enumerate() {
+                let source = (first + i / steps as usize) * 4;
+                pixel.copy_from_slice(&preview[source..source + 4]);
+            }
+        });
     }
 
-    fn get_pixel_color(&self, i: u32, j: u32, samples: u32) -> Color {
-        let depth = if self.state == State::Static && self.render_crisp {
-            self.max_depth
+    /// Draws the average of all the samples so far.
+    fn draw_film(&self, frame: &mut [u8]) {
+        if self.denoise {
+            self.film.denoised(&self.denoiser).write_rgba8(self.tone_mapper, frame);
         } else {
-            3
-        };
+            self.film.write_rgba8(self.tone_mapper, frame);
+        }
+    }
+
+    /// Adds one full depth sample to every pixel.
+    fn accumulate(&mut self) {
+        let sample = self.film.samples();
+        let mut film = std::mem::take(&mut self.film);
+
+        film.rows_mut().enumerate().for_each(|(row, (colors, aovs))| {
+            let j = self.height - row as u32 - 1;
+            for (i, (color, aov)) in colors.iter_mut().zip(aovs).enumerate() {
+                *color = *color + self.get_pixel_color(i as u32, j, sample, 1, self.max_depth, aov);
+            }
+        });
+        film.add_samples(1);
+
+        self.film = film;
+    }
+
+    /// Renders the whole image at full quality, without a window.
+    pub fn render(&self) -> Film {
+        let mut film = self.new_film(self.width, self.height);
+
+        film.rows_mut().enumerate().for_each(|(row, (colors, aovs))| {
+            let j = self.height - row as u32 - 1;
+            for (i, (color, aov)) in colors.iter_mut().zip(aovs).enumerate() {
+                *color = self.get_pixel_color(i as u32, j, 0, self.max_samples, self.max_depth, aov);
+            }
+        });
+        film.add_samples(self.max_samples);
+
+        if self.denoise {
+            return film.denoised(&self.denoiser);
+        }
+        film
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn max_samples(&self) -> u32 {
+        self.max_samples
+    }
+
+    pub fn sampler(&self) -> SamplerType {
+        self.sampler
+    }
+
+    pub fn tone_mapper(&self) -> ToneMapper {
+        self.tone_mapper
+    }
+
+    /// Sums `samples` samples of a pixel, numbered from `first_sample` so progressive
+    /// rendering picks up where the previous frame stopped.
+    ///
+    /// The auxiliary outputs of the samples are added to `aov`.
+    fn get_pixel_color(&self, i: u32, j: u32, first_sample: u32, samples: u32, depth: u32, aov: &mut Aov) -> Color {
+        let pixel = j as u64 * self.width as u64 + i as u64;
         let mut color = Color::new_black();
-        for _ in 0..samples {
-            let u = (i as f64 + rand::thread_rng().gen::<f64>()) / (self.width - 1) as f64;
-            let v = (j as f64 + rand::thread_rng().gen::<f64>()) / (self.height - 1) as f64;
-            let ray = self.camera.get_ray(u, v);
-            color = color + self.ray_color(&ray, depth);
+        for sample in first_sample..first_sample + samples {
+            let mut sample_aov = Aov::default();
+            color = color + match self.sampler {
+                SamplerType::Random => self.sample_pixel(
+                    i, j, depth, &mut RandomSampler::new(self.seed, pixel, sample), &mut sample_aov
+                ),
+                SamplerType::Stratified => self.sample_pixel(
+                    i, j, depth, &mut StratifiedSampler::new(self.seed, pixel, sample, self.max_samples), &mut sample_aov
+                ),
+                SamplerType::Halton => self.sample_pixel(
+                    i, j, depth, &mut HaltonSampler::new(self.seed, pixel, sample), &mut sample_aov
+                ),
+                SamplerType::Sobol => self.sample_pixel(
+                    i, j, depth, &mut SobolSampler::new(self.seed, pixel, sample), &mut sample_aov
+                ),
+            };
+            aov.add(&sample_aov);
         }
         
         color
     }
 
-    fn ray_color(&self, ray: &Ray, depth: u32) -> Color {
-        let mut rec = HitRecord::new();
-        if depth <= 0 {
-            return Color::new_white();
-        }
-    
-        if self.hit(ray, 0.001, INFINITY, &mut rec) {
-            let mut scattered = Ray::new(Vector::new_empty(), Vector::new_empty());
-            let mut attenuation = Color::new_black();
-            if rec.material.unwrap().scatter(ray, &rec, &mut attenuation, &mut scattered) {
-                return attenuation * self.ray_color(&scattered, depth - 1);
+    /// Traces a single sample of a pixel, the sampler picks the spot inside the pixel, on the lens and at every bounce.
+    fn sample_pixel(&self, i: u32, j: u32, depth: u32, sampler: &mut dyn Sampler, aov: &mut Aov) -> Color {
+        let (du, dv) = sampler.get_2d();
+        let u = (i as f64 + du) / (self.width - 1) as f64;
+        let v = (j as f64 + dv) / (self.height - 1) as f64;
+        let lens_sample = sampler.get_2d();
+        // Only spend a sample dimension on the time when there is motion blur, so still images stay the same.
+        let time_sample = if self.camera.has_motion_blur() { sampler.get_1d() } else { 0.0 };
+        let ray = self.camera.get_ray(u, v, lens_sample, time_sample);
+        self.ray_color(&ray, depth, sampler, aov)
+    }
+
+    /// Traces a path through the scene, accumulating emitted light weighted by the
+    /// throughput of every bounce so far. Paths that run out of depth contribute black.
+    ///
+    /// Diffuse hits also sample a point on a light and a direction towards the background, like
+    /// the sun or an environment map, and trace shadow rays towards them. Light found that way and
+    /// light found by scattering into an emitter or the background are both weighted with the
+    /// power heuristic, so each is counted once and small lights converge quickly.
+    ///
+    /// What the camera ray hits first gets recorded in `aov`.
+    fn ray_color(&self, ray: &Ray, depth: u32, sampler: &mut dyn Sampler, aov: &mut Aov) -> Color {
+        let mut radiance = Color::new_black();
+        let mut throughput = Color::new_white();
+        let mut ray = *ray;
+        // Density of the scatter that produced `ray`, `None` when lights were not sampled at its origin.
+        let mut scatter_pdf: Option<f64> = None;
+
+        for bounce in 0..depth {
+            let mut rec = HitRecord::new();
+            if !self.world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+                // A see-through background only shows up in reflections and the light it gives off.
+                if bounce == 0 && self.background.is_transparent() {
+                    return radiance;
+                }
+                let background = self.background.radiance(&ray.direction);
+                if bounce == 0 {
+                    aov.albedo = background;
+                }
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, self.background.pdf(&ray.direction)),
+                    None => 1.0
+                };
+                return radiance + throughput * background * weight;
             }
+
+            let material = rec.material.unwrap();
+            if bounce == 0 {
+                *aov = Aov {
+                    albedo: material.albedo_at(&rec),
+                    normal: rec.normal.unwrap(),
+                    position: rec.point.unwrap(),
+                    depth: rec.t.unwrap() * ray.direction.length(),
+                    hits: 1,
+                    object_id: rec.object_id,
+                    material_id: Some(material.id),
+                };
+            }
+            let emitted = material.emitted();
+            if emitted.max_component() > 0.0 {
+                let weight = match scatter_pdf {
+                    Some(pdf) => {
+                        let distance_squared = (rec.t.unwrap() * ray.direction.length()).powi(2);
+                        let cosine = rec.normal.unwrap().dot(&ray.direction.unit_vector()).abs();
+                        power_heuristic(pdf, self.lights.pdf(emitted) * distance_squared / cosine)
+                    },
+                    None => 1.0
+                };
+                radiance = radiance + throughput * emitted * weight;
+            }
+
+            if material.is_diffuse() {
+                radiance = radiance + throughput * (self.sample_light(&rec, ray.time, sampler) + self.sample_background(&rec, ray.time, sampler));
+            }
+            let scattered = match material.sample(&ray, &rec, sampler) {
+                Some(scattered) => scattered,
+                None => return radiance
+            };
+            throughput = throughput * scattered.weight;
+            scatter_pdf = (!scattered.specular).then_some(scattered.pdf);
+
+            // Russian roulette: once a path has bounced a few times, kill it with a
+            // probability based on how little it can still contribute and reweight
+            // the survivors so the estimate stays unbiased.
+            if bounce >= ROULETTE_START_DEPTH {
+                let survival = throughput.max_component().min(0.95);
+                if sampler.get_1d() >= survival {
+                    return radiance;
+                }
+                throughput = throughput * (1.0 / survival);
+            }
+
+            ray = scattered.ray;
+        }
+
+        radiance
+    }
+
+    /// Light reaching a diffuse hit straight from a point picked on one of the lights, weighted
+    /// against finding the same point by scattering. Moving lights are sampled where they are at `time`.
+    fn sample_light(&self, rec: &HitRecord, time: f64, sampler: &mut dyn Sampler) -> Color {
+        let choice = sampler.get_1d();
+        let position = sampler.get_2d();
+        let light = match self.lights.sample(choice, position, time) {
+            Some(light) => light,
+            None => return Color::new_black()
+        };
+
+        let point = rec.point.unwrap();
+        let to_light = light.point - point;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+        let cosine = light.normal.dot(&direction).abs();
+        if cosine <= 0.0 || rec.normal.unwrap().dot(&direction) <= 0.0 {
             return Color::new_black();
         }
-    
-        let unit_direction = ray.direction.unit_vector();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        return (1.0 - t) * Color::new_white() + t * Color::new(0.5, 0.7, 1.0);
+
+        let shadow = Ray::new(point, direction).with_time(time);
+        if self.world.hit(&shadow, 0.001, distance - 0.001, &mut HitRecord::new()) {
+            return Color::new_black();
+        }
+
+        let material = rec.material.unwrap();
+        let pdf = light.pdf * distance * distance / cosine;
+        let weight = power_heuristic(pdf, material.pdf(rec, &direction));
+        material.eval(rec, &direction) * light.emission * (weight / pdf)
     }
 
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
-        let mut temp_record = HitRecord::new();
-        let mut hit_anything = false;
-        let mut closest = t_max;
-    
-        for object in &self.world {
-            if object.hit(ray, t_min, closest, &mut temp_record) {
-                hit_anything = true;
-                closest = temp_record.t.unwrap();
-                *hit_record = temp_record;
-            }
+    /// Light reaching a diffuse hit from a direction picked on the background, like the sun or the bright
+    /// parts of an environment map, weighted against finding the same direction by scattering.
+    fn sample_background(&self, rec: &HitRecord, time: f64, sampler: &mut dyn Sampler) -> Color {
+        let (direction, pdf) = match self.background.sample(sampler) {
+            Some(sample) => sample,
+            None => return Color::new_black()
+        };
+        if rec.normal.unwrap().dot(&direction) <= 0.0 {
+            return Color::new_black();
         }
-    
-        hit_anything
+
+        let shadow = Ray::new(rec.point.unwrap(), direction).with_time(time);
+        if self.world.hit(&shadow, 0.001, f64::INFINITY, &mut HitRecord::new()) {
+            return Color::new_black();
+        }
+
+        let material = rec.material.unwrap();
+        let weight = power_heuristic(pdf, material.pdf(rec, &direction));
+        material.eval(rec, &direction) * self.background.radiance(&direction) * (weight / pdf)
     }
 }
 
-fn random_world() -> Vec<Sphere> {
-    let mut world: Vec<Sphere> = Vec::new();
+/// Multiple importance sampling weight of a sample drawn with density `pdf` when `other_pdf` could also have drawn it.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (pdf, other_pdf) = (pdf * pdf, other_pdf * other_pdf);
+    if pdf + other_pdf > 0.0 { pdf / (pdf + other_pdf) } else { 0.0 }
+}
+
+/// Writes an opaque RGB color into a 4 byte RGBA pixel of the frame.
+fn write_pixel(pixel: &mut [u8], color: [u8; 3]) {
+    pixel[..3].copy_from_slice(&color);
+    pixel[3] = 255;
+}
+
+fn random_world(rng: &mut Pcg32) -> HittableList {
+    let mut world = HittableList::new();
 
     let ground = Material::new_metal(Color::new(0.8, 0.8, 0.5), 0.2);
     let center_sphere = Material::new_dielectric(Color::new(0.8, 1.0, 0.8), 1.5);
     let material_left = Material::new_metal(Color::new(0.5, 0.5, 0.7), 0.2);
     let material_right = Material::new_metal(Color::new(0.8, 0.6, 0.2), 0.8);
 
-    world.push(Sphere::new(Vector::new(0.0, -1000.0, 0.0), 1000.0, ground));
-    world.push(Sphere::new(Vector::new(0.0, 1.0, 0.0), 1.0, center_sphere));
-    world.push(Sphere::new(Vector::new(-4.0, 1.0, 0.0), 1.0, material_left));
-    world.push(Sphere::new(Vector::new(4.0, 1.0, 0.0), 1.0, material_right));
+    world.add(Box::new(Sphere::new(Vector::new(0.0, -1000.0, 0.0), 1000.0, ground)));
+    world.add(Box::new(Sphere::new(Vector::new(0.0, 1.0, 0.0), 1.0, center_sphere)));
+    world.add(Box::new(Sphere::new(Vector::new(-4.0, 1.0, 0.0), 1.0, material_left)));
+    world.add(Box::new(Sphere::new(Vector::new(4.0, 1.0, 0.0), 1.0, material_right)));
 
     for i in -11..11 {
         for j in -12..5 {
-            let mut rng = rand::thread_rng();
             let mat_rng = rng.gen::<f64>();
             let center = Vector::new(i as f64 + 0.9 * rng.gen::<f64>(), 0.2, j as f64 + 0.9 * rng.gen::<f64>());
             if (center - Vector::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 let material = match mat_rng {
                     r if r < 0.2 => {
-                        let albedo = Color::random() * Color::random();
+                        let albedo = Color::random(rng) * Color::random(rng);
                         Material::new_lambertian(albedo)
                     }
                     r if r < 0.8 => {
-                        let albedo = Color::random_range(0.5,1.0);
+                        let albedo = Color::random_range(0.5,1.0, rng);
                         let fuzz = rng.gen_range(0.0..0.5);
                         Material::new_metal(albedo, fuzz)
                     }
                     _ => {
-                        let albedo = Color::random_range(0.8,1.0);
+                        let albedo = Color::random_range(0.8,1.0, rng);
                         Material::new_dielectric(albedo, 1.5)
                     }
                 };
-                world.push(Sphere::new(center, 0.2, material));
+                world.add(Box::new(Sphere::new(center, 0.2, material)));
 
             }
         }
     }
 
     world
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_is_reproducible_across_thread_counts() {
+        let mut image = Image::new(32, 18, 4, 8);
+        image.set_seed(11);
+
+        let render_with = |threads: usize| rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| image.render());
+
+        let single = render_with(1);
+        assert_eq!(single, render_with(3));
+        assert_eq!(single, image.render());
+    }
+}
@@ -0,0 +1,8 @@
+pub mod primitives;
+pub mod objects;
+pub mod shapes;
+pub mod image;
+pub mod loaders;
+pub mod textures;
+pub mod samplers;
+pub mod output;
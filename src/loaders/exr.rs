@@ -0,0 +1,353 @@
+use std::{fs, io, path::Path};
+
+use crate::primitives::color::Color;
+
+/// An image read from an OpenEXR file, with its pixels row by row from the top.
+pub struct ExrPixels {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+/// Reads the R, G and B channels of an OpenEXR file, or the Y channel of a greyscale one.
+///
+/// Only single part scanline files are supported, either uncompressed or compressed with RLE, ZIPS or ZIP,
+/// which covers what most programs write by default.
+pub fn load_exr(path: impl AsRef<Path>) -> io::Result<ExrPixels> {
+    read_exr(&fs::read(path)?)
+}
+
+pub fn read_exr(bytes: &[u8]) -> io::Result<ExrPixels> {
+    let mut input = Input { bytes, position: 0 };
+    if input.take(4)? != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(invalid("not an OpenEXR file"));
+    }
+    let version = input.u32()?;
+    if version & 0x200 != 0 {
+        return Err(invalid("tiled OpenEXR files are not supported"));
+    }
+    if version & 0x1800 != 0 {
+        return Err(invalid("multi part and deep OpenEXR files are not supported"));
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut window = None;
+    loop {
+        let name = input.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = input.string()?;
+        let size = input.i32()? as usize;
+        let mut value = Input { bytes: input.take(size)?, position: 0 };
+        match name.as_str() {
+            "channels" => channels = read_channels(&mut value)?,
+            "compression" => compression = Some(value.take(1)?[0]),
+            "dataWindow" => window = Some([value.i32()?, value.i32()?, value.i32()?, value.i32()?]),
+            _ => {}
+        }
+    }
+
+    let [x_min, y_min, x_max, y_max] = window.ok_or_else(|| invalid("missing the data window"))?;
+    if x_max < x_min || y_max < y_min {
+        return Err(invalid("empty data window"));
+    }
+    let extent = |min: i32, max: i32| max.checked_sub(min).and_then(|extent| extent.checked_add(1))
+        .map(|extent| extent as usize)
+        .ok_or_else(|| invalid("data window too large"));
+    let (width, height) = (extent(x_min, x_max)?, extent(y_min, y_max)?);
+    let compression = compression.ok_or_else(|| invalid("missing the compression"))?;
+    let lines_per_block = match compression {
+        0..=2 => 1,
+        3 => 16,
+        other => return Err(invalid(&format!(
+            "unsupported compression {} ({}), only none, RLE, ZIPS and ZIP are supported",
+            other, compression_name(other)
+        ))),
+    };
+
+    // Greyscale images only have luminance, copy it into all three colors.
+    let find = |name: &str| channels.iter().position(|channel: &Channel| channel.name == name);
+    let sources = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err(invalid("no R, G and B or Y channels")),
+    };
+
+    // Everything below is sized by the header, check the file is big enough to hold that much before allocating.
+    let pixel_size: usize = channels.iter().map(|channel| channel.size).sum();
+    let line_size = pixel_size.checked_mul(width).ok_or_else(|| invalid("data window too large"))?;
+    let image_size = line_size.checked_mul(height).ok_or_else(|| invalid("data window too large"))?;
+    let blocks = height.div_ceil(lines_per_block);
+    let remaining = bytes.len() - input.position;
+    if blocks.checked_mul(8).is_none_or(|table| table > remaining)
+        || image_size > remaining.saturating_mul(compression_ratio(compression)) {
+        return Err(invalid("data window too large for the file"));
+    }
+    let mut offsets = Vec::with_capacity(blocks);
+    for _ in 0..blocks {
+        offsets.push(input.u64()? as usize);
+    }
+
+    let mut pixels = vec![Color::new_black(); width * height];
+    for offset in offsets {
+        let mut block = Input { bytes, position: offset };
+        let first_line = block.i32()?.checked_sub(y_min);
+        let size = block.i32()? as usize;
+        let data = block.take(size)?;
+        let first_line = match first_line {
+            Some(first_line) if first_line >= 0 && (first_line as usize) < height => first_line,
+            _ => return Err(invalid("scanline block outside of the data window")),
+        };
+        let first_line = first_line as usize;
+        let lines = lines_per_block.min(height - first_line);
+        let expected = lines * line_size;
+
+        let data = if data.len() == expected {
+            data.to_vec()
+        } else {
+            decompress(compression, data, expected)?
+        };
+
+        for line in 0..lines {
+            let line_data = &data[line * line_size..(line + 1) * line_size];
+            let row = &mut pixels[(first_line + line) * width..(first_line + line + 1) * width];
+            let value = |source: usize, x: usize| {
+                let start: usize = channels[..source].iter().map(|channel| channel.size * width).sum();
+                let channel = &channels[source];
+                channel.value(&line_data[start + x * channel.size..start + (x + 1) * channel.size])
+            };
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = Color::new(value(sources[0], x), value(sources[1], x), value(sources[2], x));
+            }
+        }
+    }
+
+    Ok(ExrPixels { width: width as u32, height: height as u32, pixels })
+}
+
+struct Channel {
+    name: String,
+    pixel_type: i32,
+    size: usize,
+}
+
+impl Channel {
+    fn value(&self, bytes: &[u8]) -> f64 {
+        match self.pixel_type {
+            0 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            1 => from_half(u16::from_le_bytes([bytes[0], bytes[1]])) as f64,
+            _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        }
+    }
+}
+
+fn read_channels(input: &mut Input) -> io::Result<Vec<Channel>> {
+    let mut channels = Vec::new();
+    loop {
+        let name = input.string()?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = input.i32()?;
+        let size = match pixel_type {
+            0 | 2 => 4,
+            1 => 2,
+            other => return Err(invalid(&format!("unknown pixel type {} of channel '{}'", other, name))),
+        };
+        // pLinear and three reserved bytes.
+        input.take(4)?;
+        if input.i32()? != 1 || input.i32()? != 1 {
+            return Err(invalid(&format!("subsampled channel '{}' is not supported", name)));
+        }
+        channels.push(Channel { name, pixel_type, size });
+    }
+}
+
+fn decompress(compression: u8, data: &[u8], expected: usize) -> io::Result<Vec<u8>> {
+    let packed = match compression {
+        1 => decode_rle(data)?,
+        // The output buffer grows by doubling and may not double past the limit, so allow twice what is expected.
+        2 | 3 => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(data, expected.saturating_mul(2))
+            .map_err(|error| invalid(&format!("corrupt ZIP data: {:?}", error)))?,
+        _ => return Err(invalid("block has the wrong size for uncompressed data")),
+    };
+    if packed.len() != expected {
+        return Err(invalid("block does not decompress to the expected size"));
+    }
+
+    // Before compressing, the bytes got split into two interleaved halves and delta encoded.
+    let mut deltas = packed;
+    for i in 1..deltas.len() {
+        deltas[i] = deltas[i - 1].wrapping_add(deltas[i]).wrapping_sub(128);
+    }
+    let half = deltas.len().div_ceil(2);
+    let mut data = Vec::with_capacity(deltas.len());
+    for i in 0..half {
+        data.push(deltas[i]);
+        if half + i < deltas.len() {
+            data.push(deltas[half + i]);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Runs of repeated bytes are stored as a count and the byte, other bytes as a negative count and the bytes.
+fn decode_rle(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut input = data.iter();
+    while let Some(&count) = input.next() {
+        let count = count as i8;
+        if count < 0 {
+            for _ in 0..-(count as i32) {
+                output.push(*input.next().ok_or_else(|| invalid("truncated RLE data"))?);
+            }
+        } else {
+            let value = *input.next().ok_or_else(|| invalid("truncated RLE data"))?;
+            output.extend(std::iter::repeat_n(value, count as usize + 1));
+        }
+    }
+
+    Ok(output)
+}
+
+/// The most a block can shrink with `compression`, deflate stores at most 1032 bytes per byte and RLE 64 bytes per 2.
+fn compression_ratio(compression: u8) -> usize {
+    match compression {
+        0 => 1,
+        1 => 64,
+        _ => 1032,
+    }
+}
+
+fn compression_name(compression: u8) -> &'static str {
+    match compression {
+        4 => "PIZ",
+        5 => "PXR24",
+        6 => "B44",
+        7 => "B44A",
+        8 => "DWAA",
+        9 => "DWAB",
+        _ => "unknown",
+    }
+}
+
+/// Converts a 16 bit float to a 32 bit one, exactly.
+fn from_half(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads little endian values from a byte slice, failing instead of panicking on truncated files.
+struct Input<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(count).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(self.i32()? as u32)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let bytes = self.take(8)?;
+        let mut value = [0; 8];
+        value.copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.bytes[self.position.min(self.bytes.len())..].iter().position(|byte| *byte == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let bytes = self.take(length + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..length]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::exr::{ExrImage, ExrPixelType};
+
+    #[test]
+    fn test_reads_back_written_exr() {
+        let mut image = ExrImage::new(2, 2);
+        image.add_channel("R", ExrPixelType::Half, vec![1.0, 0.5, 0.25, 1000.0]);
+        image.add_channel("G", ExrPixelType::Float, vec![0.1, 0.2, 0.3, 0.4]);
+        image.add_channel("B", ExrPixelType::Half, vec![0.0, -2.0, 65504.0, 1.0]);
+        image.add_channel("depth.Z", ExrPixelType::Float, vec![9.0; 4]);
+
+        let mut bytes = Vec::new();
+        image.write(&mut bytes).unwrap();
+        let read = read_exr(&bytes).unwrap();
+
+        assert_eq!((read.width, read.height), (2, 2));
+        assert_eq!(read.pixels[1], Color::new(0.5, 0.2f32 as f64, -2.0));
+        assert_eq!(read.pixels[3], Color::new(1000.0, 0.4f32 as f64, 1.0));
+        assert!(read_exr(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_broken_headers() {
+        let mut image = ExrImage::new(2, 2);
+        image.add_channel("Y", ExrPixelType::Half, vec![1.0; 4]);
+        let mut bytes = Vec::new();
+        image.write(&mut bytes).unwrap();
+
+        for length in 0..bytes.len() {
+            assert!(read_exr(&bytes[..length]).is_err());
+        }
+        let mut garbage = bytes[..8].to_vec();
+        garbage.extend((0..64).map(|i| (i * 37) as u8));
+        assert!(read_exr(&garbage).is_err());
+
+        // Data windows that overflow, or that a file this small cannot hold, fail before anything gets allocated.
+        let window = bytes.windows(17).position(|name| name == b"dataWindow\0box2i\0").unwrap() + 21;
+        let with_window = |corners: [i32; 4]| {
+            let mut bytes = bytes.clone();
+            for (i, corner) in corners.iter().enumerate() {
+                bytes[window + 4 * i..window + 4 * (i + 1)].copy_from_slice(&corner.to_le_bytes());
+            }
+            read_exr(&bytes).err().map(|error| error.to_string())
+        };
+        assert_eq!(with_window([i32::MIN, 0, i32::MAX, 1]).as_deref(), Some("data window too large"));
+        assert_eq!(with_window([0, 0, 1_000_000, 1_000_000]).as_deref(), Some("data window too large for the file"));
+        assert_eq!(with_window([0, 0, 1, 1]), None);
+    }
+
+    #[test]
+    fn test_undoes_rle_and_predictor() {
+        assert_eq!(decode_rle(&[2, 7, 0xfe, 1, 2]).unwrap(), vec![7, 7, 7, 1, 2]);
+
+        // Bytes 1, 2, 3, 4 are split into 1, 3 and 2, 4, then stored as differences offset by 128.
+        let packed = [1, 130, 127, 130];
+        let compressed = [0xfc, 1, 130, 127, 130];
+        assert_eq!(decompress(1, &compressed, packed.len()).unwrap(), vec![1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod obj;
+pub mod scene;
+pub mod exr;
@@ -0,0 +1,436 @@
+use std::{collections::HashMap, fmt, fs, io, path::{Path, PathBuf}, sync::Arc};
+
+use crate::primitives::{color::Color, vector::{Vector, Vec3}};
+use crate::shapes::{material::Material, triangle_mesh::TriangleMesh};
+use crate::textures::{texture::Texture, image_texture::ImageTexture};
+
+/// Material used for faces that are not assigned one through `usemtl`.
+const DEFAULT_MATERIAL: &str = "";
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io { path: PathBuf, source: io::Error },
+    Parse { file: String, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "could not read '{}': {}", path.display(), source),
+            ObjError::Parse { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Loads a Wavefront OBJ file along with the MTL libraries it references.
+///
+/// One mesh is produced per material used in the file, `mtllib` paths are resolved relative to the OBJ file.
+pub fn load_obj(path: impl AsRef<Path>) -> Result<Vec<TriangleMesh>, ObjError> {
+    let path = path.as_ref();
+    let source = read(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    for (line_number, line) in source.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() == Some("mtllib") {
+            let library: Vec<&str> = tokens.collect();
+            if library.is_empty() {
+                return Err(parse_error(&path.display().to_string(), line_number + 1, "'mtllib' is missing a file name"));
+            }
+            let library_path = directory.join(library.join(" "));
+            let library_directory = library_path.parent().unwrap_or(directory);
+            materials.extend(parse_mtl(&read(&library_path)?, &library_path.display().to_string(), library_directory)?);
+        }
+    }
+
+    parse_obj(&source, &path.display().to_string(), &materials)
+}
+
+/// Parses the contents of an OBJ file, `file` is only used for error messages.
+pub fn parse_obj(source: &str, file: &str, materials: &HashMap<String, Material>) -> Result<Vec<TriangleMesh>, ObjError> {
+    let mut positions: Vec<Vector> = Vec::new();
+    let mut normals: Vec<Vector> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+
+    let mut groups: Vec<MeshBuilder> = Vec::new();
+    let mut current = start_group(&mut groups, DEFAULT_MATERIAL);
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line_number = line_number + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("v") => positions.push(parse_vector(tokens, file, line_number)?),
+            Some("vn") => normals.push(parse_vector(tokens, file, line_number)?),
+            Some("vt") => {
+                let values = parse_floats(tokens, file, line_number)?;
+                if values.is_empty() {
+                    return Err(parse_error(file, line_number, "'vt' needs at least one coordinate"));
+                }
+                uvs.push((values[0], values.get(1).copied().unwrap_or(0.0)));
+            },
+            Some("f") => {
+                let mut corners = Vec::new();
+                for token in tokens {
+                    corners.push(parse_corner(token, positions.len(), uvs.len(), normals.len(), file, line_number)?);
+                }
+                if corners.len() < 3 {
+                    return Err(parse_error(file, line_number, "a face needs at least three vertices"));
+                }
+
+                let group = &mut groups[current];
+                let corners: Vec<usize> = corners.into_iter()
+                    .map(|corner| group.vertex(corner, &positions, &uvs, &normals))
+                    .collect();
+                // Polygons are triangulated as a fan around their first vertex.
+                for i in 1..corners.len() - 1 {
+                    group.indices.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            },
+            Some("usemtl") => {
+                let name: Vec<&str> = tokens.collect();
+                // Unknown materials fall back to the default one, exporters often reference missing libraries.
+                let name = name.join(" ");
+                current = match groups.iter().position(|group| group.material == name) {
+                    Some(index) => index,
+                    None => start_group(&mut groups, &name),
+                };
+            },
+            // Object and group names, smoothing groups and libraries (handled by `load_obj`) do not affect the geometry.
+            // Lines, points, curves and surfaces cannot be rendered, so they get skipped like anything else unknown.
+            _ => {},
+        }
+    }
+
+    Ok(groups.into_iter()
+        .filter(|group| !group.indices.is_empty())
+        .map(|group| {
+            let material = materials.get(&group.material).cloned()
+                .unwrap_or_else(|| Material::new_lambertian(Color::new(0.8, 0.8, 0.8)));
+            group.build(material)
+        })
+        .collect())
+}
+
+/// Parses the contents of an MTL library and maps every material onto the closest built in material.
+///
+/// Emissive materials (`Ke`) become diffuse lights, materials with a refractive `illum` or that are mostly
+/// see-through (`d` below 0.5, `Tr` above 0.5) become dielectrics using `Ni`, materials with a specular color
+/// (`Ks`) that dominates or a reflective `illum` become metals with a fuzz derived from `Ns`, everything else
+/// is lambertian using `Kd`.
+/// Diffuse texture maps (`map_Kd`) are loaded relative to `directory`.
+pub fn parse_mtl(source: &str, file: &str, directory: &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut textures: HashMap<PathBuf, Arc<dyn Texture>> = HashMap::new();
+    let mut current: Option<(String, MtlParameters)> = None;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line_number = line_number + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let statement = match tokens.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        if statement == "newmtl" {
+            let name: Vec<&str> = tokens.collect();
+            if let Some((name, parameters)) = current.take() {
+                let material = parameters.to_material().with_name(&name);
+                materials.insert(name, material);
+            }
+            current = Some((name.join(" "), MtlParameters::default()));
+            continue;
+        }
+
+        let parameters = match &mut current {
+            Some((_, parameters)) => parameters,
+            None => return Err(parse_error(file, line_number, &format!("'{}' appears before any 'newmtl'", statement))),
+        };
+
+        match statement {
+            "Kd" => parameters.diffuse = parse_color(tokens, file, line_number)?,
+            "Ks" => parameters.specular = parse_color(tokens, file, line_number)?,
+            "Tf" => parameters.transmission = Some(parse_color(tokens, file, line_number)?),
+            "Ns" => parameters.shininess = parse_float(tokens, file, line_number)?,
+            "Ni" => parameters.index_of_refraction = parse_float(tokens, file, line_number)?,
+            "d" => parameters.dissolve = parse_float(tokens, file, line_number)?,
+            "Tr" => parameters.dissolve = 1.0 - parse_float(tokens, file, line_number)?,
+            "illum" => parameters.illumination = parse_float(tokens, file, line_number)? as u32,
+            "Ke" => parameters.emission = parse_color(tokens, file, line_number)?,
+            "map_Kd" => {
+                // Texture options come before the file name, which is always the last token.
+                let path = match tokens.last() {
+                    Some(name) => directory.join(name),
+                    None => return Err(parse_error(file, line_number, "'map_Kd' is missing a file name")),
+                };
+                let texture = match textures.get(&path) {
+                    Some(texture) => texture.clone(),
+                    None => {
+                        let texture: Arc<dyn Texture> = Arc::new(ImageTexture::load(&path).map_err(|error| {
+                            parse_error(file, line_number, &format!("could not load texture '{}': {}", path.display(), error))
+                        })?);
+                        textures.insert(path, texture.clone());
+                        texture
+                    }
+                };
+                parameters.diffuse_map = Some(texture);
+            },
+            // Ambient color and the other texture maps have no equivalent material parameter yet.
+            _ => {},
+        }
+    }
+
+    if let Some((name, parameters)) = current {
+        let material = parameters.to_material().with_name(&name);
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+/// Dissolve below which a material without a refractive `illum` is treated as glass.
+const DIELECTRIC_DISSOLVE: f64 = 0.5;
+
+struct MtlParameters {
+    diffuse: Color,
+    diffuse_map: Option<Arc<dyn Texture>>,
+    specular: Color,
+    transmission: Option<Color>,
+    emission: Color,
+    shininess: f64,
+    index_of_refraction: f64,
+    dissolve: f64,
+    illumination: u32,
+}
+
+impl Default for MtlParameters {
+    fn default() -> MtlParameters {
+        MtlParameters {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            diffuse_map: None,
+            specular: Color::new_black(),
+            transmission: None,
+            emission: Color::new_black(),
+            shininess: 0.0,
+            index_of_refraction: 1.5,
+            dissolve: 1.0,
+            illumination: 2,
+        }
+    }
+}
+
+impl MtlParameters {
+    fn to_material(&self) -> Material {
+        let max_component = |color: Color| color.r().max(color.g()).max(color.b());
+
+        if max_component(self.emission) > 0.0 {
+            return Material::new_diffuse_light(self.emission);
+        }
+
+        // Slightly faded materials are usually meant as a blend rather than glass, only mostly see-through ones refract.
+        if self.dissolve < DIELECTRIC_DISSOLVE || matches!(self.illumination, 4 | 6 | 7 | 9) {
+            let index_of_refraction = if self.index_of_refraction > 1.0 { self.index_of_refraction } else { 1.5 };
+            return Material::new_dielectric(self.transmission.unwrap_or_else(Color::new_white), index_of_refraction);
+        }
+
+        // A reflective `illum` without a specular color would make a black mirror, the diffuse color is closer.
+        let specular = max_component(self.specular);
+        if specular > 0.0 && (specular > max_component(self.diffuse) || matches!(self.illumination, 3 | 5 | 8)) {
+            // Phong exponents map onto a roughness through the usual Beckmann approximation.
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            return Material::new_metal(self.specular, fuzz);
+        }
+
+        match &self.diffuse_map {
+            Some(texture) => Material::new_textured_lambertian(texture.clone()),
+            None => Material::new_lambertian(self.diffuse),
+        }
+    }
+}
+
+/// The triangles of a single material, with the OBJ vertices deduplicated into one vertex buffer.
+struct MeshBuilder {
+    material: String,
+    corner_indices: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    positions: Vec<Vector>,
+    uvs: Vec<Option<(f64, f64)>>,
+    normals: Vec<Option<Vector>>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, corner: (usize, Option<usize>, Option<usize>), positions: &[Vector], uvs: &[(f64, f64)], normals: &[Vector]) -> usize {
+        if let Some(index) = self.corner_indices.get(&corner) {
+            return *index;
+        }
+
+        let (position, uv, normal) = corner;
+        let index = self.positions.len();
+        self.positions.push(positions[position]);
+        self.uvs.push(uv.map(|uv| uvs[uv]));
+        self.normals.push(normal.map(|normal| normals[normal]));
+        self.corner_indices.insert(corner, index);
+        index
+    }
+
+    fn build(self, material: Material) -> TriangleMesh {
+        let uvs: Option<Vec<(f64, f64)>> = self.uvs.into_iter().collect();
+        let normals: Option<Vec<Vector>> = self.normals.into_iter().collect();
+
+        // Indices are checked while parsing and every vertex gets its uv and normal pushed along with it.
+        let mut mesh = TriangleMesh::new(self.positions, self.indices, material).expect("face indices are validated");
+        if let Some(uvs) = uvs {
+            mesh = mesh.with_uvs(uvs).expect("one uv per vertex");
+        }
+        if let Some(normals) = normals {
+            mesh = mesh.with_normals(normals).expect("one normal per vertex");
+        }
+        mesh
+    }
+}
+
+fn start_group(groups: &mut Vec<MeshBuilder>, material: &str) -> usize {
+    groups.push(MeshBuilder {
+        material: material.to_string(),
+        corner_indices: HashMap::new(),
+        positions: Vec::new(),
+        uvs: Vec::new(),
+        normals: Vec::new(),
+        indices: Vec::new(),
+    });
+    groups.len() - 1
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io { path: path.to_path_buf(), source })
+}
+
+fn parse_error(file: &str, line: usize, message: &str) -> ObjError {
+    ObjError::Parse { file: file.to_string(), line, message: message.to_string() }
+}
+
+fn parse_floats<'a>(tokens: impl Iterator<Item = &'a str>, file: &str, line: usize) -> Result<Vec<f64>, ObjError> {
+    tokens
+        .map(|token| token.parse::<f64>().map_err(|_| parse_error(file, line, &format!("'{}' is not a number", token))))
+        .collect()
+}
+
+fn parse_float<'a>(tokens: impl Iterator<Item = &'a str>, file: &str, line: usize) -> Result<f64, ObjError> {
+    match parse_floats(tokens, file, line)?.first() {
+        Some(value) => Ok(*value),
+        None => Err(parse_error(file, line, "expected a number")),
+    }
+}
+
+fn parse_vector<'a>(tokens: impl Iterator<Item = &'a str>, file: &str, line: usize) -> Result<Vector, ObjError> {
+    let values = parse_floats(tokens, file, line)?;
+    if values.len() < 3 {
+        return Err(parse_error(file, line, "expected three coordinates"));
+    }
+    Ok(Vector::new(values[0], values[1], values[2]))
+}
+
+fn parse_color<'a>(tokens: impl Iterator<Item = &'a str>, file: &str, line: usize) -> Result<Color, ObjError> {
+    let values = parse_floats(tokens, file, line)?;
+    match values.len() {
+        // A single value is a grey color.
+        1 => Ok(Color::new(values[0], values[0], values[0])),
+        n if n >= 3 => Ok(Color::new(values[0], values[1], values[2])),
+        _ => Err(parse_error(file, line, "expected a color made of one or three numbers")),
+    }
+}
+
+/// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner into zero based indices.
+fn parse_corner(
+    token: &str, position_count: usize, uv_count: usize, normal_count: usize, file: &str, line: usize
+) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+    let mut parts = token.split('/');
+    let resolve = |part: Option<&str>, count: usize| -> Result<Option<usize>, ObjError> {
+        let part = match part {
+            Some(part) if !part.is_empty() => part,
+            _ => return Ok(None),
+        };
+        let index: i64 = part.parse()
+            .map_err(|_| parse_error(file, line, &format!("'{}' is not a valid index", part)))?;
+        // Negative indices count backwards from the most recent element.
+        let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(parse_error(file, line, &format!("index {} is out of range", index)));
+        }
+        Ok(Some(resolved as usize))
+    };
+
+    let position = match resolve(parts.next(), position_count)? {
+        Some(position) => position,
+        None => return Err(parse_error(file, line, &format!("'{}' is missing a vertex index", token))),
+    };
+    let uv = resolve(parts.next(), uv_count)?;
+    let normal = resolve(parts.next(), normal_count)?;
+
+    Ok((position, uv, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::material::MaterialType;
+
+    #[test]
+    fn test_parse_obj_triangulates_and_groups_by_material() {
+        let materials = parse_mtl("newmtl red\nKd 0.8 0.1 0.1\n\nnewmtl glass\nd 0.2\nNi 1.45\n", "test.mtl", Path::new("")).unwrap();
+        assert!(matches!(materials["red"].mat_type, MaterialType::Lambertian));
+        assert!(matches!(materials["glass"].mat_type, MaterialType::Dielectric));
+        assert!((materials["glass"].index_of_refraction - 1.45).abs() < 0.0000001);
+
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\n\
+            usemtl red\nf 1//1 2//1 3//1 4//1\nusemtl glass\nf -4 -3 -2\n";
+        let meshes = parse_obj(source, "test.obj", &materials).unwrap();
+
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].triangle_count(), 2);
+        assert_eq!(meshes[1].triangle_count(), 1);
+    }
+
+    #[test]
+    fn test_parse_mtl_picks_metals_and_dielectrics() {
+        let source = "newmtl mirror\nillum 3\nKs 0.9 0.9 0.9\n\nnewmtl unlit_mirror\nillum 3\nKd 0.2 0.4 0.6\n\n\
+            newmtl faded\nd 0.9\n\nnewmtl tinted\nTr 0.8\n\nnewmtl refractive\nillum 7\n";
+        let materials = parse_mtl(source, "test.mtl", Path::new("")).unwrap();
+
+        assert!(matches!(materials["mirror"].mat_type, MaterialType::Metal));
+        assert!(matches!(materials["unlit_mirror"].mat_type, MaterialType::Lambertian));
+        assert!(matches!(materials["faded"].mat_type, MaterialType::Lambertian));
+        assert!(matches!(materials["tinted"].mat_type, MaterialType::Dielectric));
+        assert!(matches!(materials["refractive"].mat_type, MaterialType::Dielectric));
+    }
+
+    #[test]
+    fn test_parse_obj_reports_line_numbers() {
+        let error = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n", "broken.obj", &HashMap::new()).err().unwrap();
+        assert_eq!(error.to_string(), "broken.obj:3: index 3 is out of range");
+    }
+
+    #[test]
+    fn test_parse_obj_skips_unsupported_statements() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nvp 0.5 0.5\nl 1 2 3\np 1\ncstype bezier\nf 1 2 3\n";
+        let meshes = parse_obj(source, "lines.obj", &HashMap::new()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].triangle_count(), 1);
+
+        // Statements that are handled still report malformed data.
+        let error = parse_obj("l 1 2\nv 0 zero 0\n", "broken.obj", &HashMap::new()).err().unwrap();
+        assert!(error.to_string().starts_with("broken.obj:2: "), "{}", error);
+    }
+}
@@ -0,0 +1,759 @@
+use std::{collections::HashMap, fmt, fs, io, path::{Path, PathBuf}, sync::Arc};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::primitives::{color::Color, vector::{Vector, Vec3}};
+use crate::objects::{camera::CameraSettings, environment::Environment, sky::Sky, background::Background};
+use crate::samplers::sampler::SamplerType;
+use crate::output::tone_map::ToneMapper;
+use crate::shapes::{hittable_list::HittableList, material::Material, sphere::Sphere, moving_sphere::MovingSphere, triangle::Triangle};
+use crate::textures::{texture::Texture, solid_color::SolidColor, checker::CheckerTexture, image_texture::ImageTexture};
+use super::obj::{load_obj, ObjError};
+
+/// A scene loaded from a TOML scene description.
+///
+/// ```toml
+/// # What rays that miss everything see, a blue gradient when left out. Either a "constant" color, a "gradient"
+/// # from 'bottom' to 'top', a "texture" from [textures] wrapped around the scene, or "transparent" which leaves
+/// # pixels the camera sees nothing in see-through while lighting the scene with 'color', black by default.
+/// [background]
+/// type = "gradient"
+/// bottom = [1.0, 1.0, 1.0]
+/// top = [0.5, 0.7, 1.0]
+///
+/// # An equirectangular .hdr or .exr image that lights the scene instead, replaces '[background]'.
+/// [environment]
+/// path = "studio.hdr"
+/// rotation = 90.0
+/// intensity = 1.5
+///
+/// # Or a physically based daylight sky with a sun, also replacing '[background]'.
+/// [sky]
+/// sun_elevation = 30.0
+/// sun_azimuth = 45.0
+/// turbidity = 3.0
+/// ground_albedo = [0.3, 0.3, 0.3]
+/// intensity = 1.0
+///
+/// [render]
+/// width = 800
+/// height = 450
+/// samples = 50
+/// max_depth = 50
+/// seed = 0
+/// sampler = "sobol"
+/// tone_mapping = "aces"
+/// denoise = true
+///
+/// [camera]
+/// fov = 60.0
+/// look_from = [0.0, 2.0, 4.0]
+/// look_at = [0.0, 0.0, 0.0]
+/// aperture = 0.1
+/// # The shutter stays open from 'shutter_open' to 'shutter_close', blurring moving spheres over that time.
+/// shutter_open = 0.0
+/// shutter_close = 0.5
+///
+/// [textures.checker]
+/// type = "checker"
+/// even = [0.2, 0.3, 0.1]
+/// odd = [0.9, 0.9, 0.9]
+/// scale = 1.0
+///
+/// [materials.ground]
+/// type = "lambertian"
+/// texture = "checker"
+///
+/// [[shapes]]
+/// type = "sphere"
+/// center = [0.0, -1000.0, 0.0]
+/// radius = 1000.0
+/// material = "ground"
+///
+/// # Moves from 'center' at 'start_time' to 'end_center' at 'end_time', by default as the shutter opens and closes.
+/// [[shapes]]
+/// type = "moving_sphere"
+/// center = [1.5, 0.5, 0.0]
+/// end_center = [2.0, 0.5, 0.0]
+/// radius = 0.5
+/// material = "ground"
+///
+/// [[shapes]]
+/// type = "obj"
+/// path = "teapot.obj"
+/// ```
+pub struct Scene {
+    pub world: HittableList,
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+    /// What rays that miss everything see.
+    pub background: Background,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub max_depth: u32,
+    /// Seed of the sampling, renders with the same seed come out identical.
+    pub seed: u64,
+    /// How the samples of each pixel are spread.
+    pub sampler: SamplerType,
+    /// How radiance gets mapped to displayable colors.
+    pub tone_mapper: ToneMapper,
+    /// Whether renders get denoised, guided by the auxiliary outputs.
+    pub denoise: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 800,
+            height: 450,
+            samples: 50,
+            max_depth: 50,
+            seed: 0,
+            sampler: SamplerType::default(),
+            tone_mapper: ToneMapper::default(),
+            denoise: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: PathBuf, source: io::Error },
+    Parse { file: String, line: Option<usize>, message: String },
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "could not read '{}': {}", path.display(), source),
+            SceneError::Parse { file, line: Some(line), message } => write!(f, "{}:{}: {}", file, line, message),
+            SceneError::Parse { file, line: None, message } => write!(f, "{}: {}", file, message),
+            SceneError::Obj(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Obj(error) => Some(error),
+            SceneError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> SceneError {
+        SceneError::Obj(error)
+    }
+}
+
+/// Loads a scene description, OBJ files it references are resolved relative to the scene file.
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io { path: path.to_path_buf(), source })?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse_scene(&source, &path.display().to_string(), directory)
+}
+
+/// Parses the contents of a scene description, `file` is only used for error messages.
+pub fn parse_scene(source: &str, file: &str, directory: &Path) -> Result<Scene, SceneError> {
+    let description: SceneDescription = toml::from_str(source).map_err(|error| SceneError::Parse {
+        file: file.to_string(),
+        line: error.line_col().map(|(line, _)| line + 1),
+        message: error.to_string()
+    })?;
+    let error = |offset: Option<usize>, message: String| SceneError::Parse {
+        file: file.to_string(),
+        line: offset.map(|offset| line_of(source, offset)),
+        message
+    };
+
+    let render_description = description.render.unwrap_or_default();
+    let defaults = RenderSettings::default();
+    let (width, width_offset) = value(render_description.width, defaults.width);
+    let (height, height_offset) = value(render_description.height, defaults.height);
+    let (samples, samples_offset) = value(render_description.samples, defaults.samples);
+    let (max_depth, max_depth_offset) = value(render_description.max_depth, defaults.max_depth);
+    if width < 2 {
+        return Err(error(width_offset, format!("'width' must be at least 2 pixels, got {}", width)));
+    }
+    if height < 2 {
+        return Err(error(height_offset, format!("'height' must be at least 2 pixels, got {}", height)));
+    }
+    if samples == 0 {
+        return Err(error(samples_offset, "'samples' must be at least 1".to_string()));
+    }
+    if max_depth == 0 {
+        return Err(error(max_depth_offset, "'max_depth' must be at least 1".to_string()));
+    }
+    let sampler = match &render_description.sampler {
+        Some(name) => SamplerType::from_name(name.get_ref()).ok_or_else(|| error(Some(name.start()), format!(
+            "unknown sampler '{}', expected one of 'random', 'stratified', 'halton' or 'sobol'", name.get_ref()
+        )))?,
+        None => defaults.sampler,
+    };
+    let tone_mapper = match &render_description.tone_mapping {
+        Some(name) => ToneMapper::from_name(name.get_ref()).ok_or_else(|| error(Some(name.start()), format!(
+            "unknown tone mapping '{}', expected one of 'clamp', 'reinhard', 'aces' or 'filmic'", name.get_ref()
+        )))?,
+        None => defaults.tone_mapper,
+    };
+    let seed = render_description.seed.unwrap_or(defaults.seed);
+    let denoise = render_description.denoise.unwrap_or(defaults.denoise);
+
+    let camera_description = description.camera.unwrap_or_default();
+    let defaults = CameraSettings::default();
+    let (fov, fov_offset) = value(camera_description.fov, defaults.fov);
+    if fov <= 0.0 || fov >= 180.0 {
+        return Err(error(fov_offset, format!("'fov' must be between 0 and 180 degrees, got {}", fov)));
+    }
+    let look_from = camera_description.look_from.map(vector).unwrap_or(defaults.look_from);
+    let look_at = camera_description.look_at.map(vector).unwrap_or(defaults.look_at);
+    let (aperture, aperture_offset) = value(camera_description.aperture, defaults.aperture);
+    if aperture < 0.0 {
+        return Err(error(aperture_offset, format!("'aperture' cannot be negative, got {}", aperture)));
+    }
+    // Without an explicit focus distance, whatever the camera looks at is in focus.
+    let (focus_distance, focus_offset) = value(camera_description.focus_distance, (look_from - look_at).length());
+    if focus_distance <= 0.0 {
+        return Err(error(focus_offset, format!("'focus_distance' must be positive, got {}", focus_distance)));
+    }
+    let shutter_open = camera_description.shutter_open.unwrap_or(defaults.shutter_open);
+    let (shutter_close, shutter_offset) = value(camera_description.shutter_close, shutter_open);
+    if shutter_close < shutter_open {
+        return Err(error(shutter_offset, format!(
+            "'shutter_close' cannot be before 'shutter_open' ({}), got {}", shutter_open, shutter_close
+        )));
+    }
+    let camera = CameraSettings {
+        fov,
+        look_from,
+        look_at,
+        up: camera_description.up.map(vector).unwrap_or(defaults.up),
+        aperture,
+        focus_distance,
+        shutter_open,
+        shutter_close,
+    };
+
+    let mut textures: HashMap<String, Arc<dyn Texture>> = HashMap::new();
+    for (name, texture) in description.textures {
+        let offset = Some(texture.kind.start());
+        let missing = |field: &str| error(offset, format!("texture '{}' is missing '{}'", name, field));
+
+        let built: Arc<dyn Texture> = match texture.kind.get_ref().as_str() {
+            "solid" => Arc::new(SolidColor::new(color(texture.color.ok_or_else(|| missing("color"))?))),
+            "checker" => {
+                let even = texture.even.ok_or_else(|| missing("even"))?;
+                let odd = texture.odd.ok_or_else(|| missing("odd"))?;
+                let (scale, scale_offset) = value(texture.scale, 1.0);
+                if scale <= 0.0 {
+                    return Err(error(scale_offset, format!("'scale' must be positive, got {}", scale)));
+                }
+                Arc::new(CheckerTexture::from_colors(color(even), color(odd), scale))
+            },
+            "image" => {
+                let path = directory.join(texture.path.as_ref().ok_or_else(|| missing("path"))?);
+                let image = ImageTexture::load(&path)
+                    .map_err(|message| error(offset, format!("could not load texture '{}': {}", path.display(), message)))?;
+                Arc::new(image)
+            },
+            other => return Err(error(offset, format!(
+                "unknown texture type '{}', expected one of 'solid', 'checker' or 'image'", other
+            ))),
+        };
+        textures.insert(name, built);
+    }
+
+    let backgrounds = [description.background.is_some(), description.environment.is_some(), description.sky.is_some()];
+    if backgrounds.iter().filter(|set| **set).count() > 1 {
+        return Err(error(None, "only one of '[background]', '[environment]' and '[sky]' can be set".to_string()));
+    }
+    let background = if let Some(environment) = description.environment {
+        let offset = Some(environment.path.start());
+        let (intensity, intensity_offset) = value(environment.intensity, 1.0);
+        if intensity < 0.0 {
+            return Err(error(intensity_offset, format!("'intensity' cannot be negative, got {}", intensity)));
+        }
+        let path = directory.join(environment.path.get_ref());
+        let map = Environment::load(&path)
+            .map_err(|message| error(offset, format!("could not load environment '{}': {}", path.display(), message)))?;
+        Background::Environment(map.with_rotation(environment.rotation.unwrap_or(0.0)).with_intensity(intensity))
+    } else if let Some(sky) = description.sky {
+        let (sun_elevation, elevation_offset) = value(sky.sun_elevation, 45.0);
+        if !(0.0..=90.0).contains(&sun_elevation) {
+            return Err(error(elevation_offset, format!("'sun_elevation' must be between 0 and 90 degrees, got {}", sun_elevation)));
+        }
+        let (turbidity, turbidity_offset) = value(sky.turbidity, 3.0);
+        if !(1.7..=10.0).contains(&turbidity) {
+            return Err(error(turbidity_offset, format!("'turbidity' must be between 1.7 and 10, got {}", turbidity)));
+        }
+        let (intensity, intensity_offset) = value(sky.intensity, 1.0);
+        if intensity < 0.0 {
+            return Err(error(intensity_offset, format!("'intensity' cannot be negative, got {}", intensity)));
+        }
+        let ground_albedo = color(sky.ground_albedo.unwrap_or([0.3, 0.3, 0.3]));
+        Background::Sky(Sky::new(sun_elevation, sky.sun_azimuth.unwrap_or(0.0), turbidity, ground_albedo).with_intensity(intensity))
+    } else if let Some(background) = description.background {
+        let offset = Some(background.kind.start());
+        let missing = |field: &str| error(offset, format!("{} background is missing '{}'", background.kind.get_ref(), field));
+
+        match background.kind.get_ref().as_str() {
+            "constant" => Background::Constant(color(background.color.ok_or_else(|| missing("color"))?)),
+            "gradient" => Background::Gradient {
+                bottom: color(background.bottom.ok_or_else(|| missing("bottom"))?),
+                top: color(background.top.ok_or_else(|| missing("top"))?),
+            },
+            "texture" => {
+                let texture = background.texture.ok_or_else(|| missing("texture"))?;
+                match textures.get(texture.get_ref()) {
+                    Some(texture) => Background::Texture(texture.clone()),
+                    None => return Err(error(Some(texture.start()), format!("unknown texture '{}'", texture.get_ref()))),
+                }
+            },
+            "transparent" => Background::Transparent(color(background.color.unwrap_or([0.0, 0.0, 0.0]))),
+            other => return Err(error(offset, format!(
+                "unknown background type '{}', expected one of 'constant', 'gradient', 'texture' or 'transparent'", other
+            ))),
+        }
+    } else {
+        Background::default()
+    };
+
+    let mut materials = HashMap::new();
+    for (name, material) in description.materials {
+        let offset = Some(material.kind.start());
+        // A texture replaces the albedo, so the albedo is not required when one is given.
+        let default_albedo = |default: Option<[f64; 3]>| match material.texture {
+            Some(_) => Some(default.unwrap_or([1.0, 1.0, 1.0])),
+            None => default,
+        };
+        let albedo = |default: Option<[f64; 3]>| match (&material.albedo, default_albedo(default)) {
+            (Some(albedo), _) => Ok(color(*albedo)),
+            (None, Some(default)) => Ok(color(default)),
+            (None, None) => Err(error(offset, format!("material '{}' is missing 'albedo'", name))),
+        };
+
+        let built = match material.kind.get_ref().as_str() {
+            "lambertian" => Material::new_lambertian(albedo(None)?),
+            "metal" => {
+                let (fuzz, fuzz_offset) = value(material.fuzz, 0.0);
+                if !(0.0..=1.0).contains(&fuzz) {
+                    return Err(error(fuzz_offset, format!("'fuzz' must be between 0 and 1, got {}", fuzz)));
+                }
+                Material::new_metal(albedo(None)?, fuzz)
+            },
+            "diffuse_light" => {
+                let emission = material.emission.ok_or_else(|| error(offset, format!("material '{}' is missing 'emission'", name)))?;
+                Material::new_diffuse_light(color(emission))
+            },
+            "dielectric" => {
+                let (index_of_refraction, index_offset) = match material.index_of_refraction {
+                    Some(index) => (*index.get_ref(), Some(index.start())),
+                    None => return Err(error(offset, format!("material '{}' is missing 'index_of_refraction'", name))),
+                };
+                if index_of_refraction <= 0.0 {
+                    return Err(error(index_offset, format!("'index_of_refraction' must be positive, got {}", index_of_refraction)));
+                }
+                Material::new_dielectric(albedo(Some([1.0, 1.0, 1.0]))?, index_of_refraction)
+            },
+            other => return Err(error(offset, format!(
+                "unknown material type '{}', expected one of 'lambertian', 'metal', 'dielectric' or 'diffuse_light'", other
+            ))),
+        };
+        let built = match &material.texture {
+            Some(texture) => match textures.get(texture.get_ref()) {
+                Some(texture) => built.with_texture(texture.clone()),
+                None => return Err(error(Some(texture.start()), format!("unknown texture '{}'", texture.get_ref()))),
+            },
+            None => built,
+        };
+        let built = built.with_name(&name);
+        materials.insert(name, built);
+    }
+    let material = |name: &Option<Spanned<String>>, offset: usize| -> Result<Material, SceneError> {
+        match name {
+            Some(name) => materials.get(name.get_ref()).cloned()
+                .ok_or_else(|| error(Some(name.start()), format!("unknown material '{}'", name.get_ref()))),
+            None => Err(error(Some(offset), "shape is missing 'material'".to_string())),
+        }
+    };
+
+    let mut world = HittableList::new();
+    for shape in description.shapes {
+        let offset = shape.kind.start();
+        let missing = |field: &str| error(Some(offset), format!("{} is missing '{}'", shape.kind.get_ref(), field));
+
+        match shape.kind.get_ref().as_str() {
+            "sphere" => {
+                let center = shape.center.ok_or_else(|| missing("center"))?;
+                let radius = shape.radius.as_ref().ok_or_else(|| missing("radius"))?;
+                if *radius.get_ref() <= 0.0 {
+                    return Err(error(Some(radius.start()), format!("sphere radius must be positive, got {}", radius.get_ref())));
+                }
+                world.add(Box::new(Sphere::new(vector(center), *radius.get_ref(), material(&shape.material, offset)?)));
+            },
+            "moving_sphere" => {
+                let center = shape.center.ok_or_else(|| missing("center"))?;
+                let end_center = shape.end_center.ok_or_else(|| missing("end_center"))?;
+                let radius = shape.radius.as_ref().ok_or_else(|| missing("radius"))?;
+                if *radius.get_ref() <= 0.0 {
+                    return Err(error(Some(radius.start()), format!("sphere radius must be positive, got {}", radius.get_ref())));
+                }
+                let start_time = shape.start_time.unwrap_or(camera.shutter_open);
+                let (end_time, end_offset) = value(shape.end_time, camera.shutter_close.max(start_time));
+                if end_time < start_time {
+                    return Err(error(end_offset, format!(
+                        "'end_time' cannot be before 'start_time' ({}), got {}", start_time, end_time
+                    )));
+                }
+                world.add(Box::new(MovingSphere::new(
+                    vector(center), vector(end_center), start_time, end_time, *radius.get_ref(), material(&shape.material, offset)?
+                )));
+            },
+            "triangle" => {
+                let [v0, v1, v2] = shape.vertices.ok_or_else(|| missing("vertices"))?;
+                world.add(Box::new(Triangle::new(vector(v0), vector(v1), vector(v2), material(&shape.material, offset)?)));
+            },
+            "quad" => {
+                let corner = vector(shape.corner.ok_or_else(|| missing("corner"))?);
+                let [u, v] = shape.edges.ok_or_else(|| missing("edges"))?;
+                let (u, v) = (vector(u), vector(v));
+                let material = material(&shape.material, offset)?;
+                world.add(Box::new(Triangle::new(corner, corner + u, corner + u + v, material.clone())));
+                world.add(Box::new(Triangle::new(corner, corner + u + v, corner + v, material)));
+            },
+            "obj" => {
+                let path = shape.path.as_ref().ok_or_else(|| missing("path"))?;
+                for mesh in load_obj(directory.join(path))? {
+                    world.add(Box::new(mesh));
+                }
+            },
+            other => return Err(error(Some(offset), format!(
+                "unknown shape type '{}', expected one of 'sphere', 'moving_sphere', 'triangle', 'quad' or 'obj'", other
+            ))),
+        }
+    }
+
+    Ok(Scene {
+        world,
+        camera,
+        render: RenderSettings {
+            width,
+            height,
+            samples,
+            max_depth,
+            seed,
+            sampler,
+            tone_mapper,
+            denoise,
+        },
+        background,
+    })
+}
+
+/// Converts a byte offset into a one based line number.
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+/// Unwraps an optional field, returning where it was written so errors can point at it.
+fn value<T: Copy>(field: Option<Spanned<T>>, default: T) -> (T, Option<usize>) {
+    match field {
+        Some(field) => (*field.get_ref(), Some(field.start())),
+        None => (default, None)
+    }
+}
+
+fn vector(value: [f64; 3]) -> Vector {
+    Vector::new(value[0], value[1], value[2])
+}
+
+fn color(value: [f64; 3]) -> Color {
+    Color::new(value[0], value[1], value[2])
+}
+
+// The descriptions below mirror the file layout. Fields that get validated are wrapped in `Spanned` so errors
+// can report their line, toml only records positions for values, not for whole tables.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    background: Option<BackgroundDescription>,
+    environment: Option<EnvironmentDescription>,
+    sky: Option<SkyDescription>,
+    render: Option<RenderDescription>,
+    camera: Option<CameraDescription>,
+    #[serde(default)]
+    textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    shapes: Vec<ShapeDescription>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderDescription {
+    width: Option<Spanned<u32>>,
+    height: Option<Spanned<u32>>,
+    samples: Option<Spanned<u32>>,
+    max_depth: Option<Spanned<u32>>,
+    seed: Option<u64>,
+    sampler: Option<Spanned<String>>,
+    tone_mapping: Option<Spanned<String>>,
+    denoise: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BackgroundDescription {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    color: Option<[f64; 3]>,
+    bottom: Option<[f64; 3]>,
+    top: Option<[f64; 3]>,
+    texture: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDescription {
+    path: Spanned<String>,
+    rotation: Option<f64>,
+    intensity: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDescription {
+    sun_elevation: Option<Spanned<f64>>,
+    sun_azimuth: Option<f64>,
+    turbidity: Option<Spanned<f64>>,
+    ground_albedo: Option<[f64; 3]>,
+    intensity: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    fov: Option<Spanned<f64>>,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    up: Option<[f64; 3]>,
+    aperture: Option<Spanned<f64>>,
+    focus_distance: Option<Spanned<f64>>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureDescription {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    color: Option<[f64; 3]>,
+    even: Option<[f64; 3]>,
+    odd: Option<[f64; 3]>,
+    scale: Option<Spanned<f64>>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDescription {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    albedo: Option<[f64; 3]>,
+    texture: Option<Spanned<String>>,
+    fuzz: Option<Spanned<f64>>,
+    index_of_refraction: Option<Spanned<f64>>,
+    emission: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShapeDescription {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    center: Option<[f64; 3]>,
+    end_center: Option<[f64; 3]>,
+    start_time: Option<f64>,
+    end_time: Option<Spanned<f64>>,
+    radius: Option<Spanned<f64>>,
+    vertices: Option<[[f64; 3]; 3]>,
+    corner: Option<[f64; 3]>,
+    edges: Option<[[f64; 3]; 2]>,
+    material: Option<Spanned<String>>,
+    path: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::exr::{ExrImage, ExrPixelType};
+    use crate::primitives::ray::Ray;
+    use crate::shapes::{hitrecord::HitRecord, hittable::Hittable};
+
+    const SCENE: &str = r#"
+[render]
+width = 320
+height = 240
+
+[camera]
+fov = 45.0
+look_from = [0.0, 1.0, 5.0]
+
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.glass]
+type = "dielectric"
+index_of_refraction = 1.5
+
+[[shapes]]
+type = "sphere"
+center = [0.0, -1000.0, 0.0]
+radius = 1000.0
+material = "ground"
+
+[[shapes]]
+type = "sphere"
+center = [0.0, 1.0, 0.0]
+radius = 1.0
+material = "glass"
+"#;
+
+    #[test]
+    fn test_parse_scene() {
+        let scene = parse_scene(SCENE, "test.toml", Path::new("")).unwrap();
+
+        assert_eq!(scene.world.len(), 2);
+        assert_eq!(scene.render.width, 320);
+        assert_eq!(scene.render.samples, RenderSettings::default().samples);
+        assert!((scene.camera.fov - 45.0).abs() < 0.0000001);
+        assert!((scene.camera.look_from.y - 1.0).abs() < 0.0000001);
+    }
+
+    #[test]
+    fn test_parse_scene_errors_have_line_numbers() {
+        let unknown_material = SCENE.replace("material = \"glass\"", "material = \"steel\"");
+        let error = parse_scene(&unknown_material, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:28: unknown material 'steel'");
+
+        let syntax = SCENE.replace("radius = 1.0", "radius = ");
+        let error = parse_scene(&syntax, "test.toml", Path::new("")).err().unwrap();
+        assert!(error.to_string().starts_with("test.toml:27: "), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_environment() {
+        let directory = std::env::temp_dir().join("scene_environment_test");
+        fs::create_dir_all(&directory).unwrap();
+        let mut map = ExrImage::new(2, 1);
+        map.add_channel("Y", ExrPixelType::Float, vec![0.5, 2.0]);
+        map.save(directory.join("studio.exr")).unwrap();
+
+        let lit = format!("[environment]\npath = \"studio.exr\"\nintensity = 2.0\n{}", SCENE);
+        let scene = parse_scene(&lit, "test.toml", &directory).unwrap();
+        assert!(matches!(scene.background, Background::Environment(_)));
+        // The left half of the map covers the -x side of the scene.
+        assert!((scene.background.radiance(&Vector::new(-1.0, 0.0, 0.1)).r() - 1.0).abs() < 0.0000001);
+
+        let missing_map = format!("[environment]\npath = \"missing.hdr\"\n{}", SCENE);
+        let error = parse_scene(&missing_map, "test.toml", Path::new("")).err().unwrap();
+        assert!(error.to_string().starts_with("test.toml:2: could not load environment 'missing.hdr'"), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_sky() {
+        let sky = format!("[sky]\nsun_elevation = 30.0\nturbidity = 4.0\nintensity = 0.5\n{}", SCENE);
+        let scene = parse_scene(&sky, "test.toml", Path::new("")).unwrap();
+        assert!(matches!(scene.background, Background::Sky(_)));
+        assert!(scene.background.pdf(&Vector::new(0.0, 30f64.to_radians().sin(), -30f64.to_radians().cos())) > 0.0);
+
+        let low_sun = sky.replace("sun_elevation = 30.0", "sun_elevation = -5.0");
+        let error = parse_scene(&low_sun, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:2: 'sun_elevation' must be between 0 and 90 degrees, got -5");
+
+        let hazy = sky.replace("turbidity = 4.0", "turbidity = 12.0");
+        let error = parse_scene(&hazy, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:3: 'turbidity' must be between 1.7 and 10, got 12");
+    }
+
+    #[test]
+    fn test_only_one_background_can_be_set() {
+        let both = format!("[sky]\nsun_elevation = 30.0\n\n[environment]\npath = \"studio.hdr\"\n{}", SCENE);
+        let error = parse_scene(&both, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml: only one of '[background]', '[environment]' and '[sky]' can be set");
+    }
+
+    #[test]
+    fn test_parse_texture_background() {
+        let textured = format!("[background]\ntype = \"texture\"\ntexture = \"stripes\"\n\n\
+            [textures.stripes]\ntype = \"solid\"\ncolor = [0.2, 0.4, 0.6]\n{}", SCENE);
+        let scene = parse_scene(&textured, "test.toml", Path::new("")).unwrap();
+        let color = scene.background.radiance(&Vector::new(0.0, 1.0, 0.0));
+        assert!((color.g() - 0.4).abs() < 0.0000001);
+        assert!(!scene.background.is_transparent());
+    }
+
+    #[test]
+    fn test_moving_spheres_move_over_the_shutter() {
+        let moving = SCENE.replace("fov = 45.0", "shutter_open = 0.0\nshutter_close = 0.5") + r#"
+[[shapes]]
+type = "moving_sphere"
+center = [-3.0, 1.0, 3.0]
+end_center = [3.0, 1.0, 3.0]
+radius = 0.5
+material = "ground"
+"#;
+        let scene = parse_scene(&moving, "test.toml", Path::new("")).unwrap();
+        assert_eq!(scene.world.len(), 3);
+        assert!((scene.camera.shutter_close - 0.5).abs() < 0.0000001);
+
+        // The sphere reaches its end as the shutter closes, not halfway there.
+        let down = |x: f64, time: f64| Ray::new(Vector::new(x, 5.0, 3.0), Vector::new(0.0, -1.0, 0.0)).with_time(time);
+        let mut record = HitRecord::new();
+        assert!(scene.world.hit(&down(3.0, 0.5), 0.001, f64::INFINITY, &mut record));
+        assert!((record.point.unwrap().y - 1.5).abs() < 0.0000001);
+        assert!(scene.world.hit(&down(0.0, 0.25), 0.001, f64::INFINITY, &mut record));
+        assert!((record.point.unwrap().y - 1.5).abs() < 0.0000001);
+
+        let explicit = moving.replace("radius = 0.5\n", "radius = 0.5\nstart_time = 0.25\nend_time = 0.0\n");
+        let error = parse_scene(&explicit, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:37: 'end_time' cannot be before 'start_time' (0.25), got 0");
+
+        let backwards_shutter = SCENE.replace("fov = 45.0", "shutter_open = 0.5\nshutter_close = 0.25");
+        let error = parse_scene(&backwards_shutter, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:8: 'shutter_close' cannot be before 'shutter_open' (0.5), got 0.25");
+    }
+
+    #[test]
+    fn test_parse_background_types() {
+        let transparent = format!("[background]\ntype = \"transparent\"\n{}", SCENE);
+        let scene = parse_scene(&transparent, "test.toml", Path::new("")).unwrap();
+        assert!(scene.background.is_transparent());
+        assert_eq!(scene.background.radiance(&Vector::new(0.0, 1.0, 0.0)), Color::new_black());
+
+        let gradient = format!("[background]\ntype = \"gradient\"\nbottom = [1.0, 0.0, 0.0]\n{}", SCENE);
+        let error = parse_scene(&gradient, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:2: gradient background is missing 'top'");
+
+        let unknown_background = format!("[background]\ntype = \"fog\"\n{}", SCENE);
+        let error = parse_scene(&unknown_background, "test.toml", Path::new("")).err().unwrap();
+        assert_eq!(error.to_string(), "test.toml:2: unknown background type 'fog', expected one of \
+            'constant', 'gradient', 'texture' or 'transparent'");
+    }
+}
@@ -1,10 +1,15 @@
-mod primitives;
-mod objects;
-mod shapes;
-mod image;
+mod cli;
+
+use std::{error::Error, time::Instant};
 
 use pixels::{Pixels, SurfaceTexture};
-use crate::image::Image;
+use ray_tracing_in_a_weekend::{
+    image::Image,
+    loaders::{obj::load_obj, scene::{load_scene, Scene, RenderSettings}},
+    objects::{camera::CameraSettings, background::Background},
+    output::{film::Film, aov::AovKind, exr::ExrImage, hdr::save_hdr, tone_map::ToneMapper},
+    shapes::hittable_list::HittableList
+};
 
 use winit::{
     event::*,
@@ -12,11 +17,144 @@ use winit::{
     window::WindowBuilder
 };
 
+use crate::cli::{Command, RenderOptions};
+
 const SAMPLES_PER_PIXEL: u32 = 50;
-const MAX_DEPTH: u32 = 20;
+const MAX_DEPTH: u32 = 50;
 
 fn main() {
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::usage());
+            std::process::exit(2);
+        }
+    };
+
+    match command {
+        Command::Help => println!("{}", cli::usage()),
+        Command::Render(options) => render(options),
+        Command::View { scene } => view(scene),
+    }
+}
+
+/// Loads the scene given on the command line, or returns `None` so the random world gets used.
+///
+/// TOML files are read as scene descriptions, anything else as an OBJ file viewed with the default camera.
+fn load_input(input: Option<String>) -> Option<Scene> {
+    let path = input?;
 
+    if path.ends_with(".toml") {
+        return match load_scene(&path) {
+            Ok(scene) => Some(scene),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    let meshes = match load_obj(&path) {
+        Ok(meshes) => meshes,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let mut world = HittableList::new();
+    for mesh in meshes {
+        world.add(Box::new(mesh));
+    }
+    Some(Scene {
+        world,
+        camera: CameraSettings::default(),
+        render: RenderSettings::default(),
+        background: Background::default(),
+    })
+}
+
+fn render(options: RenderOptions) {
+    let image = match load_input(options.scene.clone()) {
+        Some(mut scene) => {
+            let settings = &mut scene.render;
+            settings.width = options.width.unwrap_or(settings.width);
+            settings.height = options.height.unwrap_or(settings.height);
+            settings.samples = options.samples.unwrap_or(settings.samples);
+            settings.max_depth = options.depth.unwrap_or(settings.max_depth);
+            settings.seed = options.seed.unwrap_or(settings.seed);
+            settings.sampler = options.sampler.unwrap_or(settings.sampler);
+            settings.tone_mapper = options.tone_mapper.unwrap_or(settings.tone_mapper);
+            settings.denoise = options.denoise.unwrap_or(settings.denoise);
+            Image::from_scene(scene)
+        },
+        None => {
+            let defaults = RenderSettings::default();
+            let mut image = Image::new(
+                options.width.unwrap_or(defaults.width),
+                options.height.unwrap_or(defaults.height),
+                options.samples.unwrap_or(SAMPLES_PER_PIXEL),
+                options.depth.unwrap_or(MAX_DEPTH)
+            );
+            image.set_seed(options.seed.unwrap_or(defaults.seed));
+            image.set_sampler(options.sampler.unwrap_or(defaults.sampler));
+            image.set_tone_mapper(options.tone_mapper.unwrap_or(defaults.tone_mapper));
+            image.set_denoise(options.denoise.unwrap_or(defaults.denoise));
+            image
+        }
+    };
+
+    println!(
+        "rendering {}x{} at {} samples per pixel with the {} sampler...",
+        image.width(), image.height(), image.max_samples(), image.sampler()
+    );
+    let start = Instant::now();
+    let film = image.render();
+    println!("complete in {:?}", start.elapsed());
+
+    if let Err(error) = save(&options, &film, image.tone_mapper()) {
+        eprintln!("could not write '{}': {}", options.output.display(), error);
+        std::process::exit(1);
+    }
+    println!("saved {}", options.output.display());
+}
+
+/// Writes the film in the format picked by the extension of the output, HDR formats skip the tone mapping.
+fn save(options: &RenderOptions, film: &Film, tone_mapper: ToneMapper) -> Result<(), Box<dyn Error>> {
+    let extension = options.output.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+    match extension.to_ascii_lowercase().as_str() {
+        "exr" => {
+            let mut exr = ExrImage::new(film.width(), film.height());
+            exr.add_film("", film, options.exr_type);
+            if options.aovs {
+                for kind in AovKind::ALL {
+                    exr.add_aov(kind.name(), kind, film, options.exr_type);
+                }
+            }
+            return Ok(exr.save(&options.output)?);
+        },
+        "hdr" => save_hdr(&options.output, film)?,
+        _ => {
+            let buffer = film.to_rgba8(tone_mapper);
+            image::save_buffer(&options.output, &buffer, film.width(), film.height(), image::ColorType::Rgba8)?;
+        }
+    }
+
+    // Formats without layers get one EXR per output next to them, named like `render.albedo.exr`.
+    if options.aovs {
+        let stem = options.output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("image");
+        for kind in AovKind::ALL {
+            let mut exr = ExrImage::new(film.width(), film.height());
+            exr.add_aov("", kind, film, options.exr_type);
+            let path = options.output.with_file_name(format!("{}.{}.exr", stem, kind.name()));
+            exr.save(&path)?;
+            println!("saved {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn view(scene: Option<String>) {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
@@ -27,10 +165,15 @@ fn main() {
         Pixels::new(window_size.width, window_size.height, surface_texture).unwrap()
 
     };
-    // not needed anymore
-    drop(window_size);
-
-    let mut image = Image::new(window_size.width, window_size.height as u32, MAX_DEPTH, SAMPLES_PER_PIXEL);
+    // A scene passed on the command line replaces the random world, the window decides the image size.
+    let mut image = match load_input(scene) {
+        Some(mut scene) => {
+            scene.render.width = window_size.width;
+            scene.render.height = window_size.height;
+            Image::from_scene(scene)
+        },
+        None => Image::new(window_size.width, window_size.height, SAMPLES_PER_PIXEL, MAX_DEPTH)
+    };
 
     let mut window_focused = true;
 
@@ -59,6 +202,9 @@ fn main() {
                         pixels.resize_buffer(new_inner_size.width, new_inner_size.height);
                         image.resize(**new_inner_size);
                     },
+                    WindowEvent::CursorMoved {position, ..} => {
+                        image.set_cursor_position(*position);
+                    },
                     WindowEvent::Focused(
                         is_focused
                     ) => {
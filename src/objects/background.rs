@@ -0,0 +1,103 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crate::primitives::{color::Color, vector::{Vector, Vec3}};
+use crate::samplers::sampler::Sampler;
+use crate::textures::texture::Texture;
+use super::{environment::Environment, sky::Sky};
+
+/// What rays that escape the scene see, and the light it sends back into the scene.
+pub enum Background {
+    /// A single color in every direction.
+    Constant(Color),
+    /// Blends from `bottom` straight down to `top` straight up.
+    Gradient { bottom: Color, top: Color },
+    /// A texture wrapped around the scene like an equirectangular map, the middle of it towards -z.
+    Texture(Arc<dyn Texture>),
+    Environment(Environment),
+    Sky(Sky),
+    /// Camera rays that miss everything leave the pixel see-through, other rays see `color`.
+    Transparent(Color),
+}
+
+impl Default for Background {
+    /// White at the bottom fading into light blue overhead.
+    fn default() -> Background {
+        Background::Gradient { bottom: Color::new_white(), top: Color::new(0.5, 0.7, 1.0) }
+    }
+}
+
+impl Background {
+    /// Light arriving along a ray heading in `direction`.
+    pub fn radiance(&self, direction: &Vector) -> Color {
+        match self {
+            Background::Constant(color) | Background::Transparent(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.unit_vector().y() + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            },
+            Background::Texture(texture) => {
+                let direction = direction.unit_vector();
+                let u = (direction.x().atan2(-direction.z()) / (2.0 * PI) + 0.5).rem_euclid(1.0);
+                let v = 1.0 - direction.y().clamp(-1.0, 1.0).acos() / PI;
+                texture.value(u, v, &direction)
+            },
+            Background::Environment(environment) => environment.radiance(direction),
+            Background::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// Whether camera rays that miss everything leave their pixel see-through.
+    pub fn is_transparent(&self) -> bool {
+        matches!(self, Background::Transparent(_))
+    }
+
+    /// Picks a direction worth sending a shadow ray towards, along with its density per unit of solid angle.
+    ///
+    /// Smooth backgrounds are left to the rays scattered off surfaces, they never get sampled and leave the sampler alone.
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vector, f64)> {
+        match self {
+            Background::Environment(environment) => environment.sample(sampler.get_2d()),
+            Background::Sky(sky) => sky.sample(sampler.get_2d()),
+            _ => None,
+        }
+    }
+
+    /// Density, per unit of solid angle, of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vector) -> f64 {
+        match self {
+            Background::Environment(environment) => environment.pdf(direction),
+            Background::Sky(sky) => sky.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shows the texture coordinates it gets looked up at.
+    #[derive(Debug)]
+    struct Coordinates;
+
+    impl Texture for Coordinates {
+        fn value(&self, u: f64, v: f64, _point: &Vector) -> Color {
+            Color::new(u, v, 0.0)
+        }
+    }
+
+    #[test]
+    fn test_gradient_and_texture_backgrounds() {
+        let gradient = Background::Gradient { bottom: Color::new_black(), top: Color::new(1.0, 0.5, 0.0) };
+        assert_eq!(gradient.radiance(&Vector::new(0.0, -2.0, 0.0)), Color::new_black());
+        assert_eq!(gradient.radiance(&Vector::new(0.0, 3.0, 0.0)), Color::new(1.0, 0.5, 0.0));
+
+        // Straight ahead is the middle of the texture, +x a quarter further along and straight up its top edge.
+        let texture = Background::Texture(Arc::new(Coordinates));
+        let ahead = texture.radiance(&Vector::new(0.0, 0.0, -1.0));
+        assert!((ahead.r() - 0.5).abs() < 0.0000001 && (ahead.g() - 0.5).abs() < 0.0000001);
+        assert!((texture.radiance(&Vector::new(2.0, 0.0, 0.0)).r() - 0.75).abs() < 0.0000001);
+        assert!((texture.radiance(&Vector::new(0.0, 1.0, 0.0)).g() - 1.0).abs() < 0.0000001);
+        assert_eq!(texture.pdf(&Vector::new(0.0, 1.0, 0.0)), 0.0);
+    }
+}
@@ -1,47 +1,166 @@
 use std::f64::consts::PI;
 
-use crate::{primitives::{vector::Vector, ray::Ray}};
+use crate::{primitives::{vector::{Vector, Vec3}, ray::Ray}};
 
+/// Where the camera sits and where it looks, used to rebuild the camera whenever the view changes.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraSettings {
+    pub fov: f64,
+    pub look_from: Vector,
+    pub look_at: Vector,
+    pub up: Vector,
+    /// Diameter of the lens, 0 gives a pinhole camera with everything in focus.
+    pub aperture: f64,
+    /// Distance from the camera to the plane that is in perfect focus.
+    pub focus_distance: f64,
+    /// Times the shutter opens and closes, rays get spread between them so moving objects blur.
+    /// Equal times freeze everything at that moment.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        let look_from = Vector::new(0.0, 2.0, 4.0);
+        let look_at = Vector::new(0.0, 0.0, 0.0);
+
+        CameraSettings {
+            fov: 60.0,
+            look_from,
+            look_at,
+            up: Vector::new(0.0, 1.0, 0.0),
+            aperture: 0.0,
+            focus_distance: (look_from - look_at).length(),
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+}
+
+/// A thin lens camera, rays start on a disk of radius `lens_radius` and converge on the focus plane.
 pub struct Camera {
     pub origin: Vector,
     pub horizontal: Vector,
     pub vertical: Vector,
-    pub lower_left_corner: Vector    
+    pub lower_left_corner: Vector,
+    pub u: Vector,
+    pub v: Vector,
+    pub w: Vector,
+    pub lens_radius: f64,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
 }
 
 impl Camera {
-    pub fn from_ratio(aspect_ratio: f64, fov: f64, look_from: Vector, look_at: Vector, up: Vector) -> Camera {
+    pub fn from_ratio(
+        aspect_ratio: f64, fov: f64, look_from: Vector, look_at: Vector, up: Vector, aperture: f64, focus_distance: f64
+    ) -> Camera {
         let theta = degree_to_radian(fov);
         let h = (theta / 2.0).tan();
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
-        // let focal_length = 1.0;
 
         let w = (look_from - look_at).unit_vector();
         let u = up.cross(&w).unit_vector();
         let v = w.cross(&u);
 
+        // The viewport is placed on the focus plane, so rays from anywhere on the lens meet there.
         let origin = look_from;
-        let horizontal = viewport_width * u;
-        let vertical = viewport_height * v;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w;
+        let horizontal = focus_distance * viewport_width * u;
+        let vertical = focus_distance * viewport_height * v;
+        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_distance * w;
         
         Camera { 
             origin, 
             horizontal, 
             vertical, 
-            lower_left_corner 
+            lower_left_corner,
+            u,
+            v,
+            w,
+            lens_radius: aperture / 2.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+    pub fn from_settings(aspect_ratio: f64, settings: &CameraSettings) -> Camera {
+        let camera = Camera::from_ratio(
+            aspect_ratio,
+            settings.fov,
+            settings.look_from,
+            settings.look_at,
+            settings.up,
+            settings.aperture,
+            settings.focus_distance
+        );
+
+        Camera {
+            shutter_open: settings.shutter_open,
+            shutter_close: settings.shutter_close,
+            ..camera
+        }
+    }
+
+    /// Whether the shutter stays open for a while, in which case every ray needs a time picked for it.
+    pub fn has_motion_blur(&self) -> bool {
+        self.shutter_close > self.shutter_open
+    }
+
+    /// Returns a ray through the viewport coordinates `s` and `t`, starting from the point of the lens picked by `lens_sample`
+    /// at the moment `time_sample` of the way from the shutter opening to it closing.
+    pub fn get_ray(&self, s: f64, t: f64, lens_sample: (f64, f64), time_sample: f64) -> Ray {
+        let random_point = self.lens_radius * Vector::in_unit_disk_from_sample(lens_sample);
+        let offset = self.u * random_point.x + self.v * random_point.y;
+
+        Ray { 
+            origin: self.origin + offset, 
+            direction: self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            time: self.shutter_open + time_sample * (self.shutter_close - self.shutter_open)
+        }
+    }
+
+    /// Returns a ray through the viewport coordinates `s` and `t`, starting from the center of the lens as the shutter opens.
+    pub fn get_pinhole_ray(&self, s: f64, t: f64) -> Ray {
         Ray { 
             origin: self.origin, 
-            direction: self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin 
+            direction: self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin,
+            time: self.shutter_open
         }
     }
+
+    /// Distance from the lens to `point`, measured along the viewing direction.
+    pub fn depth_of(&self, point: Vector) -> f64 {
+        (point - self.origin).dot(&-self.w)
+    }
 }
 
 fn degree_to_radian(deg: f64) -> f64 {
     deg * PI / 180.0
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lens_rays_meet_on_the_focus_plane() {
+        let mut settings = CameraSettings::default();
+        let pinhole = Camera::from_settings(2.0, &settings);
+        let center = pinhole.get_pinhole_ray(0.3, 0.6);
+        let ray = pinhole.get_ray(0.3, 0.6, (0.9, 0.1), 0.0);
+        assert_eq!((ray.origin, ray.direction), (center.origin, center.direction));
+
+        settings.aperture = 0.5;
+        settings.focus_distance = 3.0;
+        let camera = Camera::from_settings(2.0, &settings);
+        let center = camera.get_pinhole_ray(0.3, 0.6);
+        let target = center.at(settings.focus_distance / center.direction.dot(&-camera.w));
+        assert!((camera.get_ray(0.3, 0.6, (0.9, 0.7), 0.0).origin - center.origin).length() > 0.1);
+        for lens_sample in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let ray = camera.get_ray(0.3, 0.6, lens_sample, 0.0);
+            let on_plane = ray.at(settings.focus_distance / ray.direction.dot(&-camera.w));
+            assert!((on_plane - target).length() < 0.0000001);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::{f64::consts::PI, fs::File, io::{self, BufReader}, path::Path};
+
+use image::codecs::hdr::HdrDecoder;
+
+use crate::primitives::{color::Color, vector::{Vector, Vec3}};
+use crate::loaders::exr::load_exr;
+
+/// A high dynamic range image surrounding the scene, lighting it from every direction.
+///
+/// The image is an equirectangular (latitude/longitude) map: the top row is straight up, the bottom row
+/// straight down and the middle column looks towards -z. Directions get picked in proportion to how
+/// much light the map sends from them, so small bright spots like the sun are found quickly.
+pub struct Environment {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Turns the map around the y axis, in radians.
+    rotation: f64,
+    intensity: f64,
+    /// Cumulative distribution over the rows, `height + 1` entries from 0 to 1.
+    row_cdf: Vec<f64>,
+    /// Cumulative distribution over the columns of every row, `width + 1` entries per row.
+    column_cdf: Vec<f64>,
+    /// Sum of all the pixel weights, 0 for a black map.
+    total_weight: f64,
+}
+
+impl Environment {
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> Environment {
+        assert_eq!(pixels.len(), width as usize * height as usize, "an environment map needs one color per pixel");
+        let (width, height) = (width as usize, height as usize);
+
+        let mut row_cdf = vec![0.0; height + 1];
+        let mut column_cdf = vec![0.0; height * (width + 1)];
+        for y in 0..height {
+            let sine = row_sine(y, height);
+            let columns = &mut column_cdf[y * (width + 1)..(y + 1) * (width + 1)];
+            for x in 0..width {
+                columns[x + 1] = columns[x] + pixel_weight(&pixels[y * width + x], sine);
+            }
+            row_cdf[y + 1] = row_cdf[y] + columns[width];
+            normalize(columns);
+        }
+        let total_weight = row_cdf[height];
+        normalize(&mut row_cdf);
+
+        Environment {
+            width,
+            height,
+            pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+            row_cdf,
+            column_cdf,
+            total_weight,
+        }
+    }
+
+    /// Loads an equirectangular map from a Radiance `.hdr` or an OpenEXR `.exr` file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Environment> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase()) {
+            Some(extension) if extension == "hdr" => {
+                let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+                let metadata = decoder.metadata();
+                let pixels = decoder.read_image_hdr()
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?
+                    .iter()
+                    .map(|pixel| Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+                    .collect();
+                Ok(Environment::new(metadata.width, metadata.height, pixels))
+            },
+            Some(extension) if extension == "exr" => {
+                let image = load_exr(path)?;
+                Ok(Environment::new(image.width, image.height, image.pixels))
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "expected a '.hdr' or '.exr' file")),
+        }
+    }
+
+    /// Turns the map around the y axis by `degrees`, a quarter turn brings what was ahead (-z) to +x.
+    pub fn with_rotation(mut self, degrees: f64) -> Environment {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// Scales the brightness of the whole map.
+    pub fn with_intensity(mut self, intensity: f64) -> Environment {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Light the map sends back along a ray heading in `direction`.
+    pub fn radiance(&self, direction: &Vector) -> Color {
+        let (x, y) = self.pixel_of(direction);
+        self.pixels[y * self.width + x] * self.intensity
+    }
+
+    /// Picks a direction towards the map with a point of the unit square, returning it along with its density
+    /// per unit of solid angle. `None` when the map is black.
+    pub fn sample(&self, (u, v): (f64, f64)) -> Option<(Vector, f64)> {
+        if self.total_weight <= 0.0 {
+            return None;
+        }
+
+        let (y, dy) = sample_cdf(&self.row_cdf, u);
+        let (x, dx) = sample_cdf(&self.column_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)], v);
+        let direction = self.direction_of((x as f64 + dx) / self.width as f64, (y as f64 + dy) / self.height as f64);
+        let pdf = self.pdf(&direction);
+        (pdf > 0.0).then_some((direction, pdf))
+    }
+
+    /// Density, per unit of solid angle, of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vector) -> f64 {
+        let sine = (1.0 - direction.unit_vector().y().powi(2)).max(0.0).sqrt();
+        if self.total_weight <= 0.0 || sine <= 0.0 {
+            return 0.0;
+        }
+
+        let (x, y) = self.pixel_of(direction);
+        let weight = pixel_weight(&self.pixels[y * self.width + x], row_sine(y, self.height));
+        let image_pdf = weight * self.width as f64 * self.height as f64 / self.total_weight;
+        image_pdf / (2.0 * PI * PI * sine)
+    }
+
+    fn pixel_of(&self, direction: &Vector) -> (usize, usize) {
+        let direction = direction.unit_vector();
+        let phi = direction.x().atan2(-direction.z()) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = direction.y().clamp(-1.0, 1.0).acos() / PI;
+
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        (x, y)
+    }
+
+    fn direction_of(&self, u: f64, v: f64) -> Vector {
+        let phi = 2.0 * PI * (u - 0.5) + self.rotation;
+        let theta = PI * v;
+        Vector::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+}
+
+/// Rows near the poles cover less of the sphere, so their pixels send less light.
+fn row_sine(y: usize, height: usize) -> f64 {
+    (PI * (y as f64 + 0.5) / height as f64).sin()
+}
+
+fn pixel_weight(pixel: &Color, sine: f64) -> f64 {
+    pixel.luminance().max(0.0) * sine
+}
+
+/// Scales a running sum so it ends at 1, spreading it evenly when everything is 0.
+fn normalize(cdf: &mut [f64]) {
+    let count = cdf.len() - 1;
+    let total = cdf[count];
+    for (i, value) in cdf.iter_mut().enumerate() {
+        *value = if total > 0.0 { *value / total } else { i as f64 / count as f64 };
+    }
+}
+
+/// Finds the entry of a cumulative distribution that `u` falls into, along with how far into it `u` is.
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let count = cdf.len() - 1;
+    let index = cdf.partition_point(|value| *value <= u).clamp(1, count) - 1;
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0.0 { ((u - cdf[index]) / width).clamp(0.0, 1.0) } else { 0.5 };
+    (index, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_follow_the_brightness() {
+        // A dim map with one bright pixel next to the middle column, turned to the +x side.
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); width * height];
+        pixels[4 * width + 8] = Color::new(1000.0, 1000.0, 1000.0);
+        let environment = Environment::new(width as u32, height as u32, pixels).with_rotation(90.0);
+
+        let bright = environment.direction_of(8.5 / width as f64, 4.5 / height as f64);
+        assert_eq!(environment.radiance(&bright), Color::new(1000.0, 1000.0, 1000.0));
+        assert!((bright.x() - 1.0).abs() < 0.1);
+
+        let count = 32;
+        let mut hits = 0;
+        let mut integral = 0.0;
+        for i in 0..count {
+            for j in 0..count {
+                let sample = ((i as f64 + 0.5) / count as f64, (j as f64 + 0.5) / count as f64);
+                let (direction, pdf) = environment.sample(sample).unwrap();
+                assert!((pdf - environment.pdf(&direction)).abs() < 0.0000001 * pdf);
+                if environment.radiance(&direction).r() > 1.0 {
+                    hits += 1;
+                }
+                integral += environment.radiance(&direction).r() / pdf;
+            }
+        }
+        assert!(hits > count * count * 9 / 10);
+
+        // The estimate of the light arriving over the whole sphere matches summing the pixels up.
+        let expected: f64 = (0..height)
+            .map(|y| {
+                let solid_angle = 2.0 * PI / width as f64
+                    * ((PI * y as f64 / height as f64).cos() - (PI * (y + 1) as f64 / height as f64).cos());
+                (0..width).map(|x| environment.pixels[y * width + x].r() * solid_angle).sum::<f64>()
+            })
+            .sum();
+        assert!((integral / (count * count) as f64 - expected).abs() < 0.02 * expected);
+    }
+}
@@ -1 +1,4 @@
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod environment;
+pub mod sky;
+pub mod background;
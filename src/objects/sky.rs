@@ -0,0 +1,195 @@
+use std::f64::consts::PI;
+
+use crate::primitives::{color::Color, vector::{Vector, Vec3}};
+
+/// Brightness given to 1 kcd/m², picked so a white diffuse surface in full sun comes out close to 1.
+const BRIGHTNESS_PER_KCD: f64 = 0.03;
+/// Luminance of the sun above the atmosphere, in kcd/m².
+const SUN_LUMINANCE: f64 = 1.9e6;
+/// Angular radius of the sun as seen from the ground.
+const SUN_ANGULAR_RADIUS: f64 = 0.004_651;
+/// Wavelengths, in micrometres, the red, green and blue light of the sun is attenuated at.
+const WAVELENGTHS: [f64; 3] = [0.680, 0.550, 0.440];
+
+/// The daylight sky model of Preetham, Shirley and Smits, with the sun as a small bright disk.
+///
+/// `turbidity` describes how hazy the air is, from 2 for a very clear day to 10 for a hazy one. Below the
+/// horizon the sky gives way to a diffuse ground of color `ground_albedo`, lit by the sky and the sun.
+#[derive(Clone, Copy, Debug)]
+pub struct Sky {
+    sun_direction: Vector,
+    sun_radiance: Color,
+    /// Cosine of the angular radius of the sun disk.
+    sun_cos_radius: f64,
+    ground: Color,
+    intensity: f64,
+    zenith: [f64; 3],
+    /// Perez distribution coefficients for the luminance and the x and y chromaticities.
+    coefficients: [[f64; 5]; 3],
+    /// The distribution of each of them towards the zenith, which the zenith values get divided by.
+    zenith_distribution: [f64; 3],
+}
+
+impl Sky {
+    /// A sky with the sun `sun_elevation` degrees above the horizon and `sun_azimuth` degrees around the up
+    /// axis, turning from straight ahead (-z) towards +x.
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64, ground_albedo: Color) -> Sky {
+        let (elevation, azimuth) = (sun_elevation.clamp(0.0, 90.0).to_radians(), sun_azimuth.to_radians());
+        let sun_direction = Vector::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        let sun_theta = PI / 2.0 - elevation;
+        let t = turbidity;
+
+        let coefficients = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let (theta, theta_2, theta_3) = (sun_theta, sun_theta.powi(2), sun_theta.powi(3));
+        let zenith = [
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t * t * (0.00166 * theta_3 - 0.00375 * theta_2 + 0.00209 * theta)
+                + t * (-0.02903 * theta_3 + 0.06377 * theta_2 - 0.03202 * theta + 0.00394)
+                + (0.11693 * theta_3 - 0.21196 * theta_2 + 0.06052 * theta + 0.25886),
+            t * t * (0.00275 * theta_3 - 0.00610 * theta_2 + 0.00317 * theta)
+                + t * (-0.04214 * theta_3 + 0.08970 * theta_2 - 0.04153 * theta + 0.00516)
+                + (0.15346 * theta_3 - 0.26756 * theta_2 + 0.06670 * theta + 0.26688),
+        ];
+        let zenith_distribution = coefficients.map(|coefficients| perez(&coefficients, 1.0, sun_theta.cos()));
+
+        // Sunlight loses blue to Rayleigh scattering and every color to haze, more so through the thick air near the horizon.
+        let air_mass = 1.0 / (sun_theta.cos() + 0.15 * (93.885 - sun_theta.to_degrees()).powf(-1.253));
+        let haze = 0.04608 * t - 0.04586;
+        let transmittance = WAVELENGTHS.map(|wavelength| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = haze * wavelength.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        });
+        let sun_radiance = Color::new(transmittance[0], transmittance[1], transmittance[2]) * (SUN_LUMINANCE * BRIGHTNESS_PER_KCD);
+
+        let mut sky = Sky {
+            sun_direction,
+            sun_radiance,
+            sun_cos_radius: SUN_ANGULAR_RADIUS.cos(),
+            ground: Color::new_black(),
+            intensity: 1.0,
+            zenith,
+            coefficients,
+            zenith_distribution,
+        };
+        sky.ground = ground_albedo * sky.ground_irradiance() * (1.0 / PI);
+        sky
+    }
+
+    /// Scales the brightness of the sky, the sun and the ground.
+    pub fn with_intensity(mut self, intensity: f64) -> Sky {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Light the sky, the sun or the ground sends back along a ray heading in `direction`.
+    pub fn radiance(&self, direction: &Vector) -> Color {
+        let direction = direction.unit_vector();
+        if direction.y() < 0.0 {
+            return self.ground * self.intensity;
+        }
+
+        let mut radiance = self.sky_radiance(&direction);
+        if direction.dot(&self.sun_direction) >= self.sun_cos_radius {
+            radiance = radiance + self.sun_radiance;
+        }
+        radiance * self.intensity
+    }
+
+    /// Picks a direction towards the sun disk with a point of the unit square, returning it along with its density
+    /// per unit of solid angle.
+    pub fn sample(&self, (u, v): (f64, f64)) -> Option<(Vector, f64)> {
+        let cos_theta = 1.0 - u * (1.0 - self.sun_cos_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let (tangent, bitangent) = self.sun_direction.orthonormal_basis();
+        let direction = sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * self.sun_direction;
+        Some((direction, self.sun_pdf()))
+    }
+
+    /// Density, per unit of solid angle, of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vector) -> f64 {
+        if direction.unit_vector().dot(&self.sun_direction) >= self.sun_cos_radius {
+            self.sun_pdf()
+        } else {
+            0.0
+        }
+    }
+
+    fn sun_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.sun_cos_radius))
+    }
+
+    /// The sky without the sun, for a unit direction above the horizon.
+    fn sky_radiance(&self, direction: &Vector) -> Color {
+        // Straight at the horizon the model breaks down, keep it just above.
+        let cos_theta = direction.y().max(0.001);
+        let cos_gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0);
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * perez(&self.coefficients[i], cos_theta, cos_gamma) / self.zenith_distribution[i]
+        });
+
+        // From the CIE xyY color space to linear sRGB.
+        let (big_x, big_z) = (x / y * luminance, (1.0 - x - y) / y * luminance);
+        let color = Color::new(
+            (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+            (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+            (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0)
+        );
+        color * BRIGHTNESS_PER_KCD
+    }
+
+    /// Light falling on flat ground from the sky and the sun.
+    fn ground_irradiance(&self) -> Color {
+        let (rings, segments) = (32, 64);
+        let mut irradiance = Color::new_black();
+        for ring in 0..rings {
+            let theta = (ring as f64 + 0.5) / rings as f64 * PI / 2.0;
+            let solid_angle = theta.sin() * (PI / 2.0 / rings as f64) * (2.0 * PI / segments as f64);
+            for segment in 0..segments {
+                let phi = (segment as f64 + 0.5) / segments as f64 * 2.0 * PI;
+                let direction = Vector::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                irradiance = irradiance + self.sky_radiance(&direction) * (theta.cos() * solid_angle);
+            }
+        }
+
+        let sun_solid_angle = 1.0 / self.sun_pdf();
+        irradiance + self.sun_radiance * (sun_solid_angle * self.sun_direction.y())
+    }
+}
+
+/// The Perez sky distribution for a direction `theta` away from the zenith and `gamma` away from the sun.
+fn perez(coefficients: &[f64; 5], cos_theta: f64, cos_gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    let gamma = cos_gamma.clamp(-1.0, 1.0).acos();
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sky_is_blue_and_sun_is_sampled() {
+        let sky = Sky::new(30.0, 90.0, 3.0, Color::new(0.3, 0.3, 0.3));
+        assert!((sky.sun_direction.x - 30f64.to_radians().cos()).abs() < 0.0000001);
+
+        let zenith = sky.radiance(&Vector::new(0.0, 1.0, 0.0));
+        assert!(zenith.b() > zenith.r());
+        assert!(sky.radiance(&Vector::new(0.0, -1.0, 0.0)).r() > 0.0);
+
+        // Sunlight near the horizon is redder than the sky, and much brighter.
+        let (direction, pdf) = sky.sample((0.3, 0.7)).unwrap();
+        assert!((pdf - sky.pdf(&direction)).abs() < 0.0000001 * pdf);
+        let sun = sky.radiance(&direction);
+        assert!(sun.r() > sun.b());
+        assert!(sun.luminance() > 1000.0 * zenith.luminance());
+        assert_eq!(sky.pdf(&Vector::new(0.0, 1.0, 0.0)), 0.0);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::primitives::{color::Color, vector::Vector};
+
+/// The auxiliary outputs of a pixel, what the camera rays hit first.
+///
+/// While rendering these are sums over the samples of the pixel, `Film::aov` hands out the averages.
+/// Albedo and normal are averaged over every sample, so edges stay anti-aliased like the beauty;
+/// position and depth only over the samples that hit something.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aov {
+    /// Surface color at the first hit, or the background for rays that miss.
+    pub albedo: Color,
+    /// Shading normal at the first hit, facing the camera.
+    pub normal: Vector,
+    /// World space position of the first hit.
+    pub position: Vector,
+    /// Distance from the camera to the first hit, infinite when nothing got hit.
+    pub depth: f64,
+    /// Number of samples that hit something.
+    pub hits: u32,
+    /// The object and material of the first sample that hit something.
+    pub object_id: Option<usize>,
+    pub material_id: Option<u32>,
+}
+
+impl Aov {
+    /// Adds the outputs of one more sample.
+    pub fn add(&mut self, sample: &Aov) {
+        self.albedo = self.albedo + sample.albedo;
+        self.normal = self.normal + sample.normal;
+        self.position = self.position + sample.position;
+        self.depth += sample.depth;
+        self.hits += sample.hits;
+        self.object_id = self.object_id.or(sample.object_id);
+        self.material_id = self.material_id.or(sample.material_id);
+    }
+
+    /// Turns the sums of `samples` samples into averages.
+    pub fn average(&self, samples: u32) -> Aov {
+        let samples = samples.max(1) as f64;
+        let hits = self.hits.max(1) as f64;
+        Aov {
+            albedo: self.albedo * (1.0 / samples),
+            normal: self.normal / samples,
+            position: self.position / hits,
+            depth: if self.hits == 0 { f64::INFINITY } else { self.depth / hits },
+            ..*self
+        }
+    }
+}
+
+/// One of the auxiliary outputs, as it gets written to an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AovKind {
+    Albedo,
+    Normal,
+    Position,
+    Depth,
+    /// Index of the object plus one, 0 where nothing got hit.
+    ObjectId,
+    /// Id of the named material, 0 where nothing got hit or the material has no name.
+    MaterialId,
+}
+
+impl AovKind {
+    pub const ALL: [AovKind; 6] = [
+        AovKind::Albedo, AovKind::Normal, AovKind::Position, AovKind::Depth, AovKind::ObjectId, AovKind::MaterialId
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AovKind::Albedo => "albedo",
+            AovKind::Normal => "normal",
+            AovKind::Position => "position",
+            AovKind::Depth => "depth",
+            AovKind::ObjectId => "object_id",
+            AovKind::MaterialId => "material_id",
+        }
+    }
+
+    pub fn channels(&self) -> &'static [&'static str] {
+        match self {
+            AovKind::Albedo => &["R", "G", "B"],
+            AovKind::Normal | AovKind::Position => &["X", "Y", "Z"],
+            AovKind::Depth => &["Z"],
+            AovKind::ObjectId | AovKind::MaterialId => &["id"],
+        }
+    }
+
+    /// Positions, depths and ids lose too much in 16 bit floats.
+    pub fn needs_full_precision(&self) -> bool {
+        !matches!(self, AovKind::Albedo | AovKind::Normal)
+    }
+
+    /// Values of the channels for a pixel, unused channels are 0.
+    pub fn values(&self, aov: &Aov) -> [f32; 3] {
+        match self {
+            AovKind::Albedo => [aov.albedo.r() as f32, aov.albedo.g() as f32, aov.albedo.b() as f32],
+            AovKind::Normal => [aov.normal.x as f32, aov.normal.y as f32, aov.normal.z as f32],
+            AovKind::Position => [aov.position.x as f32, aov.position.y as f32, aov.position.z as f32],
+            AovKind::Depth => [aov.depth as f32, 0.0, 0.0],
+            AovKind::ObjectId => [aov.object_id.map_or(0.0, |id| (id + 1) as f32), 0.0, 0.0],
+            AovKind::MaterialId => [aov.material_id.unwrap_or(0) as f32, 0.0, 0.0],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::vector::Vec3;
+
+    #[test]
+    fn test_average_over_samples_and_hits() {
+        let mut sum = Aov::default();
+        sum.add(&Aov { albedo: Color::new(1.0, 1.0, 1.0), ..Aov::default() });
+        assert_eq!(sum.average(1).depth, f64::INFINITY);
+
+        sum.add(&Aov {
+            albedo: Color::new(0.0, 0.5, 1.0),
+            position: Vector::new(2.0, 4.0, 6.0),
+            depth: 3.0,
+            hits: 1,
+            object_id: Some(4),
+            material_id: Some(7),
+            ..Aov::default()
+        });
+        sum.add(&Aov { object_id: Some(5), hits: 1, depth: 5.0, ..Aov::default() });
+
+        let average = sum.average(3);
+        assert_eq!(average.albedo, Color::new(1.0 / 3.0, 0.5, 2.0 / 3.0));
+        assert_eq!(average.position, Vector::new(1.0, 2.0, 3.0));
+        assert_eq!(average.depth, 4.0);
+        assert_eq!(average.object_id, Some(4));
+        assert_eq!(average.material_id, Some(7));
+    }
+}
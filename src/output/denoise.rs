@@ -0,0 +1,203 @@
+use rayon::prelude::*;
+
+use crate::primitives::{color::Color, vector::Vector};
+use super::aov::Aov;
+
+/// Weights of the 5 tap B3 spline the wavelet transform is built on.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// The darkest albedo the illumination gets divided by, keeps black surfaces from blowing up.
+const MIN_ALBEDO: f64 = 0.01;
+
+/// Edge avoiding À-trous wavelet filter, after Dammertz et al., "Edge-Avoiding À-Trous Wavelet
+/// Transform for fast Global Illumination Filtering", with the variance guided brightness test of
+/// Schied et al., "Spatiotemporal Variance-Guided Filtering".
+///
+/// Every pass blurs with the same 5x5 kernel spread twice as wide as the pass before, skipping
+/// neighbours that face another way, sit at another depth or differ in brightness by more than the
+/// noise around the pixel explains. The color is divided by the albedo first, so textures stay sharp
+/// while the lighting gets smoothed.
+#[derive(Clone, Copy, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    /// How many standard deviations of the local noise the brightness of two pixels may differ by.
+    pub sigma_color: f64,
+    /// Exponent on the cosine between two normals, higher keeps creases sharper.
+    pub normal_power: i32,
+    /// How different the depth of two pixels may be, relative to the depth and the tap distance.
+    pub sigma_depth: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 4.0,
+            normal_power: 64,
+            sigma_depth: 0.02,
+        }
+    }
+}
+
+struct Guide {
+    normal: Vector,
+    depth: f64,
+    hit: bool,
+}
+
+impl Denoiser {
+    /// Denoises an image of `width` by `height` average colors, row by row, guided by their average auxiliary outputs.
+    pub fn denoise(&self, width: u32, height: u32, colors: &[Color], aovs: &[Aov]) -> Vec<Color> {
+        let (width, height) = (width as usize, height as usize);
+        let albedo: Vec<Color> = aovs.iter()
+            .map(|aov| Color::new(
+                aov.albedo.r().max(MIN_ALBEDO),
+                aov.albedo.g().max(MIN_ALBEDO),
+                aov.albedo.b().max(MIN_ALBEDO)
+            ))
+            .collect();
+        let guides: Vec<Guide> = aovs.iter()
+            .map(|aov| Guide {
+                normal: if aov.normal.near_zero() { aov.normal } else { aov.normal.unit_vector() },
+                depth: aov.depth,
+                hit: aov.hits > 0,
+            })
+            .collect();
+
+        let mut illumination: Vec<Color> = colors.iter().zip(&albedo)
+            .map(|(color, albedo)| Color::new(color.r() / albedo.r(), color.g() / albedo.g(), color.b() / albedo.b()))
+            .collect();
+        let mut variance = local_variance(width, height, &illumination, &guides);
+        let mut filtered = vec![(Color::new_black(), 0.0); illumination.len()];
+
+        for iteration in 0..self.iterations {
+            let step = 1usize << iteration;
+            let brightness: Vec<f64> = illumination.iter().map(Color::luminance).collect();
+
+            filtered.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+                for (x, output) in row.iter_mut().enumerate() {
+                    let center = y * width + x;
+                    let tolerance = self.sigma_color * variance[center].sqrt() + 1e-6;
+                    let mut sum = Color::new_black();
+                    let mut variance_sum = 0.0;
+                    let mut total = 0.0;
+
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as isize + (dy as isize - 2) * step as isize;
+                        if qy < 0 || qy >= height as isize {
+                            continue;
+                        }
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (dx as isize - 2) * step as isize;
+                            if qx < 0 || qx >= width as isize {
+                                continue;
+                            }
+                            let neighbour = qy as usize * width + qx as usize;
+
+                            let (normal, depth) = match self.geometry_weight(&guides[center], &guides[neighbour], step) {
+                                Some(geometry) => geometry,
+                                None => continue,
+                            };
+                            let difference = (brightness[center] - brightness[neighbour]).abs();
+                            let weight = kx * ky * normal * (-depth - difference / tolerance).exp();
+
+                            sum = sum + illumination[neighbour] * weight;
+                            variance_sum += weight * weight * variance[neighbour];
+                            total += weight;
+                        }
+                    }
+
+                    // The center pixel always has a positive weight, unless the geometry weight underflowed.
+                    *output = if total > 0.0 {
+                        (sum * (1.0 / total), variance_sum / (total * total))
+                    } else {
+                        (illumination[center], variance[center])
+                    };
+                }
+            });
+
+            for (index, (color, filtered_variance)) in filtered.iter().enumerate() {
+                illumination[index] = *color;
+                variance[index] = *filtered_variance;
+            }
+        }
+
+        illumination.iter().zip(&albedo)
+            .map(|(light, albedo)| *light * *albedo)
+            .collect()
+    }
+
+    /// The normal weight and the exponent of the depth weight of a neighbour, `None` when only one of them hit something.
+    fn geometry_weight(&self, center: &Guide, neighbour: &Guide, step: usize) -> Option<(f64, f64)> {
+        match (center.hit, neighbour.hit) {
+            (false, false) => Some((1.0, 0.0)),
+            (true, true) => {
+                let normal = center.normal.dot(&neighbour.normal).max(0.0).powi(self.normal_power);
+                let depth = (center.depth - neighbour.depth).abs() / (self.sigma_depth * center.depth * step as f64 + 1e-8);
+                Some((normal, depth))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Variance of the brightness in the 5x5 window around every pixel, over the neighbours that agree on hitting something.
+fn local_variance(width: usize, height: usize, illumination: &[Color], guides: &[Guide]) -> Vec<f64> {
+    let mut variance = vec![0.0; illumination.len()];
+    variance.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+        for (x, output) in row.iter_mut().enumerate() {
+            let center = y * width + x;
+            let (mut sum, mut squares, mut count) = (0.0, 0.0, 0.0);
+            for qy in y.saturating_sub(2)..(y + 3).min(height) {
+                for qx in x.saturating_sub(2)..(x + 3).min(width) {
+                    let neighbour = qy * width + qx;
+                    if guides[neighbour].hit == guides[center].hit {
+                        let brightness = illumination[neighbour].luminance();
+                        sum += brightness;
+                        squares += brightness * brightness;
+                        count += 1.0;
+                    }
+                }
+            }
+            let mean = sum / count;
+            *output = (squares / count - mean * mean).max(0.0);
+        }
+    });
+    variance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::vector::Vec3;
+
+    #[test]
+    fn test_flat_noise_gets_smoothed_but_edges_kept() {
+        let (width, height) = (16, 8);
+        let mut colors = Vec::new();
+        let mut aovs = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                // Checkerboard noise on two walls that meet at a crease in the middle.
+                let noise = if (x + y) % 2 == 0 { 0.3 } else { 0.7 };
+                let left = x < width / 2;
+                colors.push(Color::new(noise, noise, noise) * if left { 1.0 } else { 0.1 });
+                aovs.push(Aov {
+                    albedo: Color::new_white(),
+                    normal: if left { Vector::new(1.0, 0.0, 0.0) } else { Vector::new(0.0, 0.0, 1.0) },
+                    depth: 5.0,
+                    hits: 1,
+                    ..Aov::default()
+                });
+            }
+        }
+
+        let denoised = Denoiser::default().denoise(width as u32, height as u32, &colors, &aovs);
+        for y in 0..height {
+            for x in 0..width {
+                let expected = if x < width / 2 { 0.5 } else { 0.05 };
+                assert!((denoised[y * width + x].r() - expected).abs() < expected * 0.2);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use super::{aov::AovKind, film::Film};
+
+/// How the values of an EXR channel are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExrPixelType {
+    /// 16 bit floats, plenty for color and half the size.
+    #[default]
+    Half,
+    /// 32 bit floats, for data like depth or positions that need the precision.
+    Float,
+}
+
+impl ExrPixelType {
+    pub fn from_name(name: &str) -> Option<ExrPixelType> {
+        match name {
+            "half" => Some(ExrPixelType::Half),
+            "float" => Some(ExrPixelType::Float),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ExrPixelType::Half => 2,
+            ExrPixelType::Float => 4,
+        }
+    }
+}
+
+struct ExrChannel {
+    name: String,
+    pixel_type: ExrPixelType,
+    /// One value per pixel, row by row from the top.
+    values: Vec<f32>,
+}
+
+/// A multi channel OpenEXR image, written as uncompressed scanlines.
+///
+/// Channels named `layer.R`, `layer.G` and so on show up as layers in compositing programs, the
+/// unprefixed `R`, `G` and `B` channels are the main image.
+pub struct ExrImage {
+    width: u32,
+    height: u32,
+    channels: Vec<ExrChannel>,
+}
+
+impl ExrImage {
+    pub fn new(width: u32, height: u32) -> ExrImage {
+        ExrImage {
+            width,
+            height,
+            channels: Vec::new()
+        }
+    }
+
+    /// Adds a channel with one value per pixel, row by row from the top.
+    pub fn add_channel(&mut self, name: &str, pixel_type: ExrPixelType, values: Vec<f32>) {
+        assert_eq!(values.len(), self.width as usize * self.height as usize, "an EXR channel needs one value per pixel");
+        self.channels.push(ExrChannel {
+            name: name.to_string(),
+            pixel_type,
+            values
+        });
+    }
+
+    /// Adds the averaged radiance of a film as the R, G and B channels of `layer`, or of the main image when `layer` is empty.
+    ///
+    /// Transparent films also get an A channel with their coverage, the colors stay premultiplied like EXR expects.
+    pub fn add_film(&mut self, layer: &str, film: &Film, pixel_type: ExrPixelType) {
+        let prefix = if layer.is_empty() { String::new() } else { format!("{}.", layer) };
+        let mut channels = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+        for y in 0..film.height() {
+            for x in 0..film.width() {
+                let color = film.pixel(x, y);
+                channels[0].push(color.r() as f32);
+                channels[1].push(color.g() as f32);
+                channels[2].push(color.b() as f32);
+                channels[3].push(film.alpha(x, y) as f32);
+            }
+        }
+
+        let [r, g, b, a] = channels;
+        self.add_channel(&format!("{}R", prefix), pixel_type, r);
+        self.add_channel(&format!("{}G", prefix), pixel_type, g);
+        self.add_channel(&format!("{}B", prefix), pixel_type, b);
+        if film.is_transparent() {
+            self.add_channel(&format!("{}A", prefix), pixel_type, a);
+        }
+    }
+
+    /// Adds an auxiliary output of a film as the channels of `layer`, or as unprefixed channels when `layer` is empty.
+    ///
+    /// Outputs that need the precision are always stored as 32 bit floats.
+    pub fn add_aov(&mut self, layer: &str, kind: AovKind, film: &Film, pixel_type: ExrPixelType) {
+        let prefix = if layer.is_empty() { String::new() } else { format!("{}.", layer) };
+        let pixel_type = if kind.needs_full_precision() { ExrPixelType::Float } else { pixel_type };
+        let mut channels = vec![Vec::new(); kind.channels().len()];
+        for y in 0..film.height() {
+            for x in 0..film.width() {
+                let values = kind.values(&film.aov(x, y));
+                for (channel, value) in channels.iter_mut().zip(values) {
+                    channel.push(value);
+                }
+            }
+        }
+
+        for (name, values) in kind.channels().iter().zip(channels) {
+            self.add_channel(&format!("{}{}", prefix, name), pixel_type, values);
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        // Readers expect the channels sorted by name, both in the header and in every scanline.
+        let mut channels: Vec<&ExrChannel> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+        header.extend_from_slice(&2i32.to_le_bytes());
+
+        let mut channel_list = Vec::new();
+        for channel in &channels {
+            channel_list.extend_from_slice(channel.name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend_from_slice(&channel.pixel_type.id().to_le_bytes());
+            // pLinear and three reserved bytes, then the x and y sampling.
+            channel_list.extend_from_slice(&[0, 0, 0, 0]);
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+
+        let mut window = Vec::new();
+        for value in [0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+
+        attribute(&mut header, "channels", "chlist", &channel_list);
+        attribute(&mut header, "compression", "compression", &[0]);
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        // Every scanline is its own block, the offset table points at each of them.
+        let line_size: usize = channels.iter().map(|channel| channel.pixel_type.size() * self.width as usize).sum();
+        let block_size = 8 + line_size;
+        let first_block = header.len() + 8 * self.height as usize;
+        writer.write_all(&header)?;
+        for y in 0..self.height as usize {
+            writer.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+        }
+
+        let mut line = Vec::with_capacity(line_size);
+        for y in 0..self.height as usize {
+            line.clear();
+            let row = y * self.width as usize..(y + 1) * self.width as usize;
+            for channel in &channels {
+                for &value in &channel.values[row.clone()] {
+                    match channel.pixel_type {
+                        ExrPixelType::Half => line.extend_from_slice(&to_half(value).to_le_bytes()),
+                        ExrPixelType::Float => line.extend_from_slice(&value.to_le_bytes()),
+                    }
+                }
+            }
+            writer.write_all(&(y as i32).to_le_bytes())?;
+            writer.write_all(&(line_size as i32).to_le_bytes())?;
+            writer.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Converts to a 16 bit float, rounding to the nearest even value.
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity stays infinity and NaN stays NaN.
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Too small for a normal half, shift the mantissa with its implicit bit into a subnormal.
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        return sign | round_shifted(mantissa, shift) as u16;
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity.
+    sign | round_shifted(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+fn round_shifted(value: u32, shift: u32) -> u32 {
+    let round_bit = 1 << (shift - 1);
+    let shifted = value >> shift;
+    if value & round_bit != 0 && value & (3 * round_bit - 1) != 0 {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_conversion() {
+        assert_eq!(to_half(1.0), 0x3c00);
+        assert_eq!(to_half(-2.0), 0xc000);
+        assert_eq!(to_half(0.5), 0x3800);
+        assert_eq!(to_half(65504.0), 0x7bff);
+        assert_eq!(to_half(1.0e6), 0x7c00);
+        assert_eq!(to_half(2f32.powi(-24)), 0x0001);
+        assert_eq!(to_half(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(to_half(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn test_exr_layout() {
+        let mut image = ExrImage::new(2, 1);
+        image.add_channel("G", ExrPixelType::Float, vec![1.0, 2.0]);
+        image.add_channel("B", ExrPixelType::Half, vec![0.5, 1.0]);
+
+        let mut bytes = Vec::new();
+        image.write(&mut bytes).unwrap();
+
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        // A single scanline, half B before float G.
+        let line = [0x00, 0x38, 0x00, 0x3c, 0, 0, 0x80, 0x3f, 0, 0, 0, 0x40];
+        assert_eq!(&bytes[bytes.len() - line.len()..], &line);
+        let offset = u64::from_le_bytes(bytes[bytes.len() - line.len() - 16..bytes.len() - line.len() - 8].try_into().unwrap());
+        assert_eq!(offset as usize, bytes.len() - line.len() - 8);
+    }
+}
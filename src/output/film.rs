@@ -0,0 +1,164 @@
+use rayon::prelude::*;
+
+use crate::primitives::color::Color;
+use super::{aov::Aov, denoise::Denoiser, tone_map::ToneMapper};
+
+/// A floating point image that collects the samples of a render before they are tone mapped.
+///
+/// Keeps the sum of every sample taken per pixel, so radiance above 1 survives until the image gets
+/// converted for display or written to disk. The auxiliary outputs of each pixel are collected alongside.
+///
+/// Over a transparent background the radiance is premultiplied: samples that miss everything add nothing,
+/// and the alpha of each pixel is the share of its samples that hit something.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Film {
+    width: u32,
+    height: u32,
+    /// Sum of the samples of each pixel, row by row from the top.
+    pixels: Vec<Color>,
+    aovs: Vec<Aov>,
+    samples: u32,
+    /// Whether pixels are only covered where the camera rays hit something.
+    transparent: bool,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![Color::new_black(); width as usize * height as usize],
+            aovs: vec![Aov::default(); width as usize * height as usize],
+            samples: 0,
+            transparent: false
+        }
+    }
+
+    /// Leaves the pixels see-through where the camera rays miss everything, for rendering over a transparent background.
+    pub fn with_transparency(mut self, transparent: bool) -> Film {
+        self.transparent = transparent;
+        self
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of samples every pixel has received.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Throws away every sample.
+    pub fn clear(&mut self) {
+        self.pixels.par_iter_mut().for_each(|color| *color = Color::new_black());
+        self.aovs.par_iter_mut().for_each(|aov| *aov = Aov::default());
+        self.samples = 0;
+    }
+
+    /// The rows of sums of the samples and of the auxiliary outputs, from the top, for adding new
+    /// samples to in parallel; call `add_samples` once they are in.
+    pub fn rows_mut(&mut self) -> impl IndexedParallelIterator<Item = (&mut [Color], &mut [Aov])> {
+        let width = self.width as usize;
+        self.pixels.par_chunks_mut(width).zip(self.aovs.par_chunks_mut(width))
+    }
+
+    pub fn add_samples(&mut self, samples: u32) {
+        self.samples += samples;
+    }
+
+    /// Average radiance of the pixel in column `x` and row `y`, counting rows from the top.
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let sum = self.pixels[(y * self.width + x) as usize];
+        if self.samples == 0 {
+            return sum;
+        }
+        sum * (1.0 / self.samples as f64)
+    }
+
+    /// Coverage of the pixel in column `x` and row `y`, from 0 where every sample missed to 1 where all of them hit.
+    ///
+    /// Always 1 unless the film is transparent.
+    pub fn alpha(&self, x: u32, y: u32) -> f64 {
+        pixel_alpha(self.transparent, &self.aovs[(y * self.width + x) as usize], self.samples)
+    }
+
+    /// Average auxiliary outputs of the pixel in column `x` and row `y`, counting rows from the top.
+    pub fn aov(&self, x: u32, y: u32) -> Aov {
+        self.aovs[(y * self.width + x) as usize].average(self.samples)
+    }
+
+    /// A copy of the film with its radiance denoised, guided by the auxiliary outputs.
+    pub fn denoised(&self, denoiser: &Denoiser) -> Film {
+        let samples = self.samples.max(1) as f64;
+        let colors: Vec<Color> = self.pixels.par_iter().map(|sum| *sum * (1.0 / samples)).collect();
+        let aovs: Vec<Aov> = self.aovs.par_iter().map(|aov| aov.average(self.samples)).collect();
+        let denoised = denoiser.denoise(self.width, self.height, &colors, &aovs);
+
+        Film {
+            width: self.width,
+            height: self.height,
+            pixels: denoised.into_iter().map(|color| color * samples).collect(),
+            aovs: self.aovs.clone(),
+            samples: self.samples,
+            transparent: self.transparent
+        }
+    }
+
+    /// Tone maps the film into 8 bit sRGB, writing RGBA pixels row by row from the top into `frame`.
+    ///
+    /// The colors are not premultiplied, partly covered pixels keep the full color of what covers them.
+    pub fn write_rgba8(&self, tone_mapper: ToneMapper, frame: &mut [u8]) {
+        let scale = if self.samples == 0 { 1.0 } else { 1.0 / self.samples as f64 };
+        let pixels = self.pixels.par_iter().zip(self.aovs.par_iter());
+        frame.par_chunks_mut(4).zip(pixels).for_each(|(pixel, (sum, aov))| {
+            let alpha = pixel_alpha(self.transparent, aov, self.samples);
+            let color = if alpha > 0.0 { *sum * (scale / alpha) } else { Color::new_black() };
+            pixel[..3].copy_from_slice(&tone_mapper.to_srgb8(color));
+            pixel[3] = (alpha * 255.0).round() as u8;
+        });
+    }
+
+    /// Tone maps the film into a new buffer of 8 bit sRGB RGBA pixels.
+    pub fn to_rgba8(&self, tone_mapper: ToneMapper) -> Vec<u8> {
+        let mut buffer = vec![0; self.pixels.len() * 4];
+        self.write_rgba8(tone_mapper, &mut buffer);
+        buffer
+    }
+}
+
+fn pixel_alpha(transparent: bool, aov: &Aov, samples: u32) -> f64 {
+    if !transparent || samples == 0 {
+        return 1.0;
+    }
+    (aov.hits as f64 / samples as f64).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alpha_follows_coverage() {
+        let mut film = Film::new(2, 1).with_transparency(true);
+        film.rows_mut().for_each(|(colors, aovs)| {
+            // One of four samples hit something red in the first pixel, the second one saw nothing.
+            colors[0] = Color::new(1.0, 0.0, 0.0);
+            aovs[0].hits = 1;
+        });
+        film.add_samples(4);
+
+        assert!((film.alpha(0, 0) - 0.25).abs() < 0.0000001);
+        assert_eq!(film.alpha(1, 0), 0.0);
+        assert_eq!(film.to_rgba8(ToneMapper::Clamp), vec![255, 0, 0, 64, 0, 0, 0, 0]);
+        assert_eq!(film.clone().with_transparency(false).alpha(1, 0), 1.0);
+    }
+}
@@ -0,0 +1,19 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use ::image::{codecs::hdr::HdrEncoder, ImageResult, Rgb};
+
+use super::film::Film;
+
+/// Writes the averaged radiance of a film as a Radiance `.hdr` (RGBE) file.
+pub fn save_hdr(path: impl AsRef<Path>, film: &Film) -> ImageResult<()> {
+    let mut pixels = Vec::with_capacity((film.width() * film.height()) as usize);
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            let color = film.pixel(x, y);
+            pixels.push(Rgb([color.r() as f32, color.g() as f32, color.b() as f32]));
+        }
+    }
+
+    let writer = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(writer).encode(&pixels, film.width() as usize, film.height() as usize)
+}
@@ -0,0 +1,6 @@
+pub mod film;
+pub mod aov;
+pub mod tone_map;
+pub mod denoise;
+pub mod exr;
+pub mod hdr;
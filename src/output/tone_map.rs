@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::primitives::color::Color;
+
+/// Squeezes the unbounded radiance of a render into the displayable range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapper {
+    /// Cuts every channel off at 1, anything brighter clips to white.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on the luminance, keeps the hue of bright colors.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// Hable's filmic curve from Uncharted 2, with a soft toe and shoulder.
+    Filmic,
+}
+
+impl ToneMapper {
+    pub fn from_name(name: &str) -> Option<ToneMapper> {
+        match name {
+            "clamp" => Some(ToneMapper::Clamp),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "aces" => Some(ToneMapper::Aces),
+            "filmic" => Some(ToneMapper::Filmic),
+            _ => None,
+        }
+    }
+
+    /// The tone mapper after this one, for cycling through them in the viewer.
+    pub fn next(&self) -> ToneMapper {
+        match self {
+            ToneMapper::Clamp => ToneMapper::Reinhard,
+            ToneMapper::Reinhard => ToneMapper::Aces,
+            ToneMapper::Aces => ToneMapper::Filmic,
+            ToneMapper::Filmic => ToneMapper::Clamp,
+        }
+    }
+
+    /// Maps linear radiance to linear display values between 0 and 1.
+    pub fn map(&self, color: Color) -> Color {
+        let color = Color::new(color.r().max(0.0), color.g().max(0.0), color.b().max(0.0));
+        let mapped = match self {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => {
+                let luminance = color.luminance();
+                if luminance <= 0.0 {
+                    return Color::new_black();
+                }
+                color * (1.0 / (1.0 + luminance))
+            },
+            ToneMapper::Aces => channels(color, |x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)),
+            ToneMapper::Filmic => {
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE_POINT: f64 = 11.2;
+                let white_scale = 1.0 / hable(WHITE_POINT);
+                channels(color, |x| hable(EXPOSURE_BIAS * x) * white_scale)
+            },
+        };
+        channels(mapped, |x| x.clamp(0.0, 1.0))
+    }
+
+    /// Tone maps linear radiance and encodes it as 8 bit sRGB.
+    pub fn to_srgb8(&self, color: Color) -> [u8; 3] {
+        let color = self.map(color);
+        [quantize(srgb_encode(color.r())), quantize(srgb_encode(color.g())), quantize(srgb_encode(color.b()))]
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::Aces => "aces",
+            ToneMapper::Filmic => "filmic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The sRGB transfer function, from linear light between 0 and 1 to the encoded value.
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `srgb_encode`.
+pub fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn quantize(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn channels(color: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(color.r()), f(color.g()), f(color.b()))
+}
+
+/// Hable's curve, before normalizing by the white point.
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for i in 0..=100 {
+            let linear = i as f64 / 100.0;
+            assert!((srgb_decode(srgb_encode(linear)) - linear).abs() < 0.0000001);
+        }
+        assert_eq!(ToneMapper::Clamp.to_srgb8(Color::new(0.0, 0.5, 4.0)), [0, 188, 255]);
+    }
+
+    #[test]
+    fn test_tone_mappers_keep_highlights_in_range() {
+        for mapper in [ToneMapper::Clamp, ToneMapper::Reinhard, ToneMapper::Aces, ToneMapper::Filmic] {
+            let dim = mapper.map(Color::new(0.5, 0.5, 0.5));
+            let bright = mapper.map(Color::new(50.0, 50.0, 50.0));
+            assert!(bright.r() <= 1.0 && bright.r() >= dim.r());
+            assert_eq!(mapper.map(Color::new_black()), Color::new_black());
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::primitives::{ray::Ray, vector::{Vector, Vec3}};
+
+/// An axis aligned bounding box, described by its two opposite corners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub minimum: Vector,
+    pub maximum: Vector,
+}
+
+impl Aabb {
+    pub fn new(minimum: Vector, maximum: Vector) -> Aabb {
+        Aabb {
+            minimum,
+            maximum
+        }
+    }
+
+    /// A box containing nothing, surrounding it with another box returns the other box.
+    pub fn empty() -> Aabb {
+        Aabb {
+            minimum: Vector::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            maximum: Vector::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)
+        }
+    }
+
+    pub fn centroid(&self) -> Vector {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.maximum - self.minimum;
+        if extent.x < 0.0 || extent.y < 0.0 || extent.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Grows the box so that it contains `point`.
+    pub fn include(&self, point: Vector) -> Aabb {
+        Aabb::surrounding_box(self, &Aabb::new(point, point))
+    }
+
+    /// Returns the smallest box that contains both `box_0` and `box_1`.
+    pub fn surrounding_box(box_0: &Aabb, box_1: &Aabb) -> Aabb {
+        let minimum = Vector::new(
+            box_0.minimum.x.min(box_1.minimum.x),
+            box_0.minimum.y.min(box_1.minimum.y),
+            box_0.minimum.z.min(box_1.minimum.z)
+        );
+        let maximum = Vector::new(
+            box_0.maximum.x.max(box_1.maximum.x),
+            box_0.maximum.y.max(box_1.maximum.y),
+            box_0.maximum.z.max(box_1.maximum.z)
+        );
+
+        Aabb {
+            minimum,
+            maximum
+        }
+    }
+
+    /// Slab test, returns true if the ray passes through the box between `t_min` and `t_max`.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let inverse_direction = Vector::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        self.hit_inverse(&ray.origin, &inverse_direction, t_min, t_max)
+    }
+
+    /// Same as `hit`, but takes the reciprocal of the ray direction so it can be reused across many boxes.
+    pub fn hit_inverse(&self, origin: &Vector, inverse_direction: &Vector, mut t_min: f64, mut t_max: f64) -> bool {
+        for axis in 0..3 {
+            let mut t0 = (self.minimum[axis] - origin[axis]) * inverse_direction[axis];
+            let mut t1 = (self.maximum[axis] - origin[axis]) * inverse_direction[axis];
+            if inverse_direction[axis] < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -3,7 +3,7 @@ use std::ops::{self};
 use super::vector::{Vector, Vec3};
 use rand::Rng;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Color {
     r: f64,
     g: f64,
@@ -41,41 +41,55 @@ impl Color {
         }
     }
 
+    pub fn r(&self) -> f64 {
+        self.r
+    }
+
+    pub fn g(&self) -> f64 {
+        self.g
+    }
+
+    pub fn b(&self) -> f64 {
+        self.b
+    }
+
+    /// Relative luminance of a linear Rec. 709 color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Largest of the three channels.
+    pub fn max_component(&self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
     pub fn add(&mut self, color: Color) -> Self{
         self.r += color.r;
         self.g += color.g;
         self.b += color.b;
 
-        return *self;
+        *self
     }
 
-    pub fn random() -> Self {
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Color {
-            r: rand::thread_rng().gen::<f64>(),
-            g: rand::thread_rng().gen::<f64>(),
-            b: rand::thread_rng().gen::<f64>()
+            r: rng.gen::<f64>(),
+            g: rng.gen::<f64>(),
+            b: rng.gen::<f64>()
         }
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
+    pub fn random_range<R: Rng + ?Sized>(min: f64, max: f64, rng: &mut R) -> Self {
         Color {
-            r: rand::thread_rng().gen_range(min..max),
-            g: rand::thread_rng().gen_range(min..max),
-            b: rand::thread_rng().gen_range(min..max)
+            r: rng.gen_range(min..max),
+            g: rng.gen_range(min..max),
+            b: rng.gen_range(min..max)
         }
     }
 
     pub fn write_color(&self) -> String {
         format!("{} {} {}\n", (256.0 * clamp(self.r, 0.0, 0.999)) as u8, (256.0 * clamp(self.g, 0.0, 0.999)) as u8, (256.0 * clamp(self.b, 0.0, 0.999)) as u8)
     }
-
-    pub fn pixels(&self, samples_per_pixel: u32) -> [u8; 3] {
-        let r = (self.r / samples_per_pixel as f64).sqrt();
-        let g = (self.g / samples_per_pixel as f64).sqrt();
-        let b = (self.b / samples_per_pixel as f64).sqrt();
-
-        [(256.0 * clamp(r, 0.0, 0.999)) as u8, (256.0 * clamp(g, 0.0, 0.999)) as u8, (256.0 * clamp(b, 0.0, 0.999)) as u8]
-    }
 }
 
 impl ops::Add<Color> for Color {
@@ -105,7 +119,7 @@ impl ops::Add<Vector> for Color {
 impl ops::Index<usize> for Color {
     type Output = f64;
 
-    fn index<'a>(&'a self, index: usize) -> &'a f64 {
+    fn index(&self, index: usize) -> &f64 {
         match index {
             1 => {&self.r},
             2 => {&self.g},
@@ -116,7 +130,7 @@ impl ops::Index<usize> for Color {
 }
 
 impl ops::IndexMut<usize> for Color {
-    fn index_mut<'a>(&'a mut self, index: usize) -> &'a mut f64 {
+    fn index_mut(&mut self, index: usize) -> &mut f64 {
         match index {
             1 => {&mut self.r},
             2 => {&mut self.g},
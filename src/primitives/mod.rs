@@ -1,3 +1,4 @@
 pub mod color;
 pub mod vector;
-pub mod ray;
\ No newline at end of file
+pub mod ray;
+pub mod aabb;
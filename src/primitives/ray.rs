@@ -4,18 +4,25 @@ use crate::primitives::vector::{Vector};
 pub struct Ray {
     pub origin: Vector,
     pub direction: Vector,
+    /// The moment the ray is traced at, moving objects are intersected where they are at that time.
+    pub time: f64,
 }
 
 impl Ray {
     pub fn new(origin: Vector, direction: Vector) -> Ray {
         Ray {
             origin,
-            direction
+            direction,
+            time: 0.0
         }
     }
 
+    pub fn with_time(mut self, time: f64) -> Ray {
+        self.time = time;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Vector {
         self.origin + t * self.direction
     }
 }
-
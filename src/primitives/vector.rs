@@ -8,11 +8,11 @@ pub trait Vec3 {
     fn x(&self) -> f64;
     fn y(&self) -> f64;
     fn z(&self) -> f64;
-    fn random() -> Self;
-    fn random_with_constraint(min: f64,  max: f64) -> Self;
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self;
+    fn random_with_constraint<R: Rng + ?Sized>(min: f64,  max: f64, rng: &mut R) -> Self;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vector {
     pub x: f64,
     pub y: f64,
@@ -48,19 +48,19 @@ impl Vec3 for Vector {
         self.z
     }
 
-    fn random() -> Self {
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Vector {
-            x: rand::thread_rng().gen::<f64>(),
-            y: rand::thread_rng().gen::<f64>(),
-            z: rand::thread_rng().gen::<f64>()
+            x: rng.gen::<f64>(),
+            y: rng.gen::<f64>(),
+            z: rng.gen::<f64>()
         }
     }
 
-    fn random_with_constraint(min: f64, max: f64) -> Self {
+    fn random_with_constraint<R: Rng + ?Sized>(min: f64, max: f64, rng: &mut R) -> Self {
         Vector {
-            x: rand::thread_rng().gen_range(min..max),
-            y: rand::thread_rng().gen_range(min..max),
-            z: rand::thread_rng().gen_range(min..max)
+            x: rng.gen_range(min..max),
+            y: rng.gen_range(min..max),
+            z: rng.gen_range(min..max)
         }
     }
 }
@@ -90,12 +90,12 @@ impl Vector {
     }
 
     pub fn unit_vector(&self) -> Vector {
-        self.clone() / self.length()
+        *self / self.length()
     }
 
-    pub fn random_unit_vector() -> Vector {
+    pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Vector {
         loop {
-            let p = Vector::random_with_constraint(-1.0, 1.0);
+            let p = Vector::random_with_constraint(-1.0, 1.0, rng);
             if p.length_squared() >= 1.0 {
                 continue;
             }
@@ -103,6 +103,56 @@ impl Vector {
         }
     }
 
+    /// Maps a point of the unit square onto the unit sphere, evenly spread over its surface.
+    pub fn unit_vector_from_sample((u, v): (f64, f64)) -> Vector {
+        let z = 1.0 - 2.0 * u;
+        let radius = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * v;
+        Vector::new(radius * phi.cos(), radius * phi.sin(), z)
+    }
+
+    /// Maps a point of the unit square into the unit disk on the xy plane.
+    ///
+    /// Uses the concentric mapping of Shirley and Chiu, which keeps neighbouring samples neighbours so
+    /// stratified samples stay stratified on the disk.
+    pub fn in_unit_disk_from_sample((u, v): (f64, f64)) -> Vector {
+        let a = 2.0 * u - 1.0;
+        let b = 2.0 * v - 1.0;
+        if a == 0.0 && b == 0.0 {
+            return Vector::new_empty();
+        }
+
+        let quarter = std::f64::consts::FRAC_PI_4;
+        let (radius, theta) = if a.abs() > b.abs() {
+            (a, quarter * (b / a))
+        } else {
+            (b, 2.0 * quarter - quarter * (a / b))
+        };
+        Vector::new(radius * theta.cos(), radius * theta.sin(), 0.0)
+    }
+
+    /// Maps a point of the unit square onto the hemisphere around the unit vector `normal`, with a
+    /// density proportional to the cosine of the angle to the normal.
+    ///
+    /// Lifts the concentric disk mapping up onto the hemisphere (Malley's method).
+    pub fn cosine_direction_from_sample(normal: &Vector, sample: (f64, f64)) -> Vector {
+        let disk = Vector::in_unit_disk_from_sample(sample);
+        let height = (1.0 - disk.length_squared()).max(0.0).sqrt();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        disk.x * tangent + disk.y * bitangent + height * *normal
+    }
+
+    /// Two unit vectors perpendicular to this unit vector and to each other, from Duff et al.
+    pub fn orthonormal_basis(&self) -> (Vector, Vector) {
+        let sign = 1.0f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vector::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vector::new(b, sign + self.y * self.y * a, -self.y)
+        )
+    }
+
     pub fn near_zero(&self) -> bool {
         let zero = 1e-8;
         self.x.abs() < zero && self.y.abs() < zero && self.z.abs() < zero 
@@ -221,7 +271,7 @@ impl ops::Neg for Vector {
 impl ops::Index<usize> for Vector {
     type Output = f64;
 
-    fn index<'a>(&'a self, index: usize) -> &'a f64 {
+    fn index(&self, index: usize) -> &f64 {
         match index {
             0 => {&self.x},
             1 => {&self.y},
@@ -232,7 +282,7 @@ impl ops::Index<usize> for Vector {
 }
 
 impl ops::IndexMut<usize> for Vector {
-    fn index_mut<'a>(&'a mut self, index: usize) -> &'a mut f64 {
+    fn index_mut(&mut self, index: usize) -> &mut f64 {
         match index {
             0 => {&mut self.x},
             1 => {&mut self.y},
@@ -279,19 +329,19 @@ impl Vec3 for PointOffset {
         self.z
     }
 
-    fn random() -> Self {
+    fn random<R: Rng + ?Sized>(rng: &mut R) -> Self {
         PointOffset {
-            x: rand::thread_rng().gen::<f64>(),
-            y: rand::thread_rng().gen::<f64>(),
-            z: rand::thread_rng().gen::<f64>()
+            x: rng.gen::<f64>(),
+            y: rng.gen::<f64>(),
+            z: rng.gen::<f64>()
         }
     }
 
-    fn random_with_constraint(min: f64, max: f64) -> Self {
+    fn random_with_constraint<R: Rng + ?Sized>(min: f64, max: f64, rng: &mut R) -> Self {
         PointOffset {
-            x: rand::thread_rng().gen_range(min..max),
-            y: rand::thread_rng().gen_range(min..max),
-            z: rand::thread_rng().gen_range(min..max)
+            x: rng.gen_range(min..max),
+            y: rng.gen_range(min..max),
+            z: rng.gen_range(min..max)
         }
     }
 }
@@ -382,5 +432,22 @@ mod tests {
         assert!(point.z - z < 0.0000001);
     }
 
+    #[test]
+    fn test_cosine_directions_stay_in_the_hemisphere() {
+        let normal = Vector::new(1.0, -2.0, 0.5).unit_vector();
+        let count = 64;
+        let mut total_cosine = 0.0;
+        for i in 0..count {
+            for j in 0..count {
+                let sample = ((i as f64 + 0.5) / count as f64, (j as f64 + 0.5) / count as f64);
+                let direction = Vector::cosine_direction_from_sample(&normal, sample);
+                assert!((direction.length() - 1.0).abs() < 0.0000001);
+                assert!(direction.dot(&normal) >= 0.0);
+                total_cosine += direction.dot(&normal);
+            }
+        }
 
+        // The mean cosine of a cosine weighted hemisphere is 2/3.
+        assert!((total_cosine / (count * count) as f64 - 2.0 / 3.0).abs() < 0.005);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+use rand::Rng;
+
+use super::{pcg::{Pcg32, mix}, sampler::Sampler};
+
+/// Bases of the Halton dimensions, dimensions past the table fall back to random numbers.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+/// The Halton sequence, every dimension the radical inverse of the sample index in its own prime base.
+///
+/// All pixels walk the same sequence, so every pixel shifts each dimension by its own random offset
+/// (a Cranley-Patterson rotation) to keep neighbouring pixels from sharing the same pattern.
+pub struct HaltonSampler {
+    seed: u64,
+    index: u32,
+    dimension: usize,
+    rng: Pcg32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64, pixel: u64, sample: u32) -> HaltonSampler {
+        HaltonSampler {
+            seed: mix(seed ^ mix(pixel)),
+            index: sample,
+            dimension: 0,
+            rng: Pcg32::for_sample(seed, pixel, sample as u64)
+        }
+    }
+
+    fn next(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return self.rng.gen();
+        }
+
+        let offset = (mix(self.seed ^ dimension as u64) >> 11) as f64 / (1u64 << 53) as f64;
+        let value = radical_inverse(self.index, PRIMES[dimension]) + offset;
+        if value >= 1.0 { value - 1.0 } else { value }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+/// Mirrors the digits of `index` in `base` around the decimal point.
+fn radical_inverse(mut index: u32, base: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut value = 0.0;
+    while index > 0 {
+        value += (index % base) as f64 * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+    // Rounding can land exactly on 1 for long digit strings.
+    value.min(1.0 - f64::EPSILON / 2.0)
+}
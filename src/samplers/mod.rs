@@ -0,0 +1,6 @@
+pub mod pcg;
+pub mod sampler;
+pub mod random;
+pub mod stratified;
+pub mod halton;
+pub mod sobol;
@@ -0,0 +1,26 @@
+use rand::Rng;
+
+use super::{pcg::Pcg32, sampler::Sampler};
+
+/// Every dimension is an independent uniform random number.
+pub struct RandomSampler {
+    rng: Pcg32,
+}
+
+impl RandomSampler {
+    pub fn new(seed: u64, pixel: u64, sample: u32) -> RandomSampler {
+        RandomSampler {
+            rng: Pcg32::for_sample(seed, pixel, sample as u64)
+        }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
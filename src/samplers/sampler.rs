@@ -0,0 +1,81 @@
+use std::fmt;
+
+/// Hands out the numbers in `[0, 1)` used to build one sample of one pixel.
+///
+/// Every call moves on to the next dimension of the sample: the pixel position comes first, then the
+/// lens, then the scattering at every bounce. Low discrepancy samplers spread each dimension evenly
+/// over all the samples of a pixel instead of drawing it independently.
+pub trait Sampler {
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// Which sampler a render uses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplerType {
+    /// Independent uniform random numbers.
+    Random,
+    /// Jittered strata, shuffled per dimension.
+    Stratified,
+    /// Randomly rotated Halton sequence.
+    Halton,
+    /// Owen scrambled Sobol sequence, padded from 2D pairs.
+    #[default]
+    Sobol,
+}
+
+impl SamplerType {
+    pub fn from_name(name: &str) -> Option<SamplerType> {
+        match name {
+            "random" => Some(SamplerType::Random),
+            "stratified" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SamplerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SamplerType::Random => "random",
+            SamplerType::Stratified => "stratified",
+            SamplerType::Halton => "halton",
+            SamplerType::Sobol => "sobol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samplers::{random::RandomSampler, stratified::StratifiedSampler, halton::HaltonSampler, sobol::SobolSampler};
+
+    /// Counts how many of the first 16 samples land in each cell of a 4x4 grid, for a dimension past the pixel and lens.
+    fn strata(mut sampler: impl FnMut(u32) -> Box<dyn Sampler>) -> Vec<u32> {
+        let mut counts = vec![0; 16];
+        for sample in 0..16 {
+            let mut sampler = sampler(sample);
+            sampler.get_2d();
+            sampler.get_1d();
+            let (x, y) = sampler.get_2d();
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            counts[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_samples_stay_in_unit_square() {
+        strata(|sample| Box::new(RandomSampler::new(3, 17, sample)));
+        strata(|sample| Box::new(HaltonSampler::new(3, 17, sample)));
+    }
+
+    #[test]
+    fn test_stratified_and_sobol_cover_every_stratum() {
+        assert_eq!(strata(|sample| Box::new(StratifiedSampler::new(3, 17, sample, 16))), vec![1; 16]);
+        assert_eq!(strata(|sample| Box::new(SobolSampler::new(3, 17, sample))), vec![1; 16]);
+    }
+}
@@ -0,0 +1,82 @@
+use super::{pcg::mix, sampler::Sampler};
+
+/// The first two Sobol dimensions with hash based Owen scrambling, padded to any number of dimensions.
+///
+/// Every 2D (or 1D) request gets its own scramble of the sample index and of the output, following
+/// Burley, "Practical Hash-based Owen Scrambling". Each pair keeps the stratification of a Sobol
+/// (0, 2)-sequence while the pairs stay uncorrelated with each other.
+pub struct SobolSampler {
+    seed: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64, pixel: u64, sample: u32) -> SobolSampler {
+        SobolSampler {
+            seed: mix(seed ^ mix(pixel)),
+            index: sample,
+            dimension: 0
+        }
+    }
+
+    fn next_seed(&mut self) -> u32 {
+        let seed = mix(self.seed ^ self.dimension) as u32;
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_1d(&mut self) -> f64 {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.index, seed);
+        to_unit(nested_uniform_scramble(index.reverse_bits(), hash_combine(seed, 0)))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.index, seed);
+        (
+            to_unit(nested_uniform_scramble(index.reverse_bits(), hash_combine(seed, 0))),
+            to_unit(nested_uniform_scramble(sobol_second_dimension(index), hash_combine(seed, 1)))
+        )
+    }
+}
+
+/// The second Sobol dimension, its generator matrix is built from the primitive polynomial x + 1.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+/// Owen scrambling of the bits of `value`, every bit gets flipped based on the bits above it.
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+/// A hash where each bit only depends on the bits below it, which reversed gives Owen scrambling.
+fn laine_karras_permutation(mut value: u32, seed: u32) -> u32 {
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50b47c);
+    value ^= value.wrapping_mul(0xb82f1e52);
+    value ^= value.wrapping_mul(0xc7afe638);
+    value ^= value.wrapping_mul(0x8d22f6e6);
+    value
+}
+
+fn hash_combine(seed: u32, value: u32) -> u32 {
+    seed ^ (value.wrapping_add(0x9e3779b9).wrapping_add(seed << 6).wrapping_add(seed >> 2))
+}
+
+fn to_unit(value: u32) -> f64 {
+    value as f64 / (1u64 << 32) as f64
+}
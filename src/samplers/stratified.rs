@@ -0,0 +1,113 @@
+use rand::Rng;
+
+use super::{pcg::{Pcg32, mix}, sampler::Sampler};
+
+/// Splits every dimension into one stratum per sample and jitters inside it.
+///
+/// The samples of a pixel visit the strata of each dimension in a different shuffled order, so the
+/// dimensions do not line up with each other. Past `samples_per_pixel` samples, as happens while the
+/// viewer keeps refining, a fresh shuffle starts.
+pub struct StratifiedSampler {
+    seed: u64,
+    index: u32,
+    round: u32,
+    samples_per_pixel: u32,
+    dimension: u64,
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, pixel: u64, sample: u32, samples_per_pixel: u32) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        StratifiedSampler {
+            seed: mix(seed ^ mix(pixel)),
+            index: sample % samples_per_pixel,
+            round: sample / samples_per_pixel,
+            samples_per_pixel,
+            dimension: 0,
+            rng: Pcg32::for_sample(seed, pixel, sample as u64)
+        }
+    }
+
+    /// The stratum this sample takes out of `count`, shuffled differently for every dimension.
+    fn stratum(&mut self, count: u32) -> u32 {
+        let hash = mix(self.seed ^ mix(self.dimension ^ ((self.round as u64) << 32)));
+        self.dimension += 1;
+        permute(self.index, count, hash as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        (stratum as f64 + self.rng.gen::<f64>()) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // The closest grid to a square with at least one cell per sample.
+        let columns = (self.samples_per_pixel as f64).sqrt() as u32;
+        let rows = self.samples_per_pixel.div_ceil(columns);
+        let stratum = self.stratum(columns * rows);
+
+        (
+            ((stratum % columns) as f64 + self.rng.gen::<f64>()) / columns as f64,
+            ((stratum / columns) as f64 + self.rng.gen::<f64>()) / rows as f64
+        )
+    }
+}
+
+/// Maps `index` to a position in a random permutation of `0..length` chosen by `seed`.
+///
+/// From Kensler, "Correlated Multi-Jittered Sampling", which walks the cycle of a hash until it falls
+/// inside the range, so the permutation never has to be stored.
+fn permute(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+
+    // Reducing the seed first keeps the sum from overflowing, wrapping instead would skew the rotation.
+    ((index as u64 + (seed % length) as u64) % length as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permute_is_a_permutation_for_any_seed() {
+        for seed in [0, 12345, u32::MAX - 3, u32::MAX] {
+            let mut seen = [false; 10];
+            for index in 0..10 {
+                seen[permute(index, 10, seed) as usize] = true;
+            }
+            assert!(seen.iter().all(|seen| *seen), "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use crate::primitives::{ray::Ray, aabb::Aabb, vector::{Vector, Vec3}};
+use super::{hittable::Hittable, hitrecord::HitRecord, hittable_list::HittableList, light::Light};
+
+/// Number of buckets the centroids are binned into when evaluating split candidates.
+const SAH_BUCKETS: usize = 12;
+/// Leaves never get split below this many primitives.
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of visiting an interior node relative to intersecting a single primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+/// Depth past which nodes get split at the median instead, so badly spread primitives cannot make the tree too deep.
+const SAH_MAX_DEPTH: usize = 32;
+/// Nodes a traversal can have queued at once, one more than the deepest the tree gets.
+const TRAVERSAL_STACK_SIZE: usize = 64;
+
+/// A node of the flattened tree.
+///
+/// Leaves reference `count` primitives starting at `offset` in the primitive order, interior nodes have
+/// their left child right after them and their right child at `offset`.
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    offset: usize,
+    count: usize,
+    axis: usize,
+}
+
+/// A bounding volume hierarchy over a set of primitive bounding boxes, built with the surface area heuristic.
+///
+/// The tree only stores primitive indices, so it can be used over any list of primitives
+/// (the objects of the world, or the triangles of a mesh).
+pub struct BvhTree {
+    nodes: Vec<BvhNode>,
+    primitive_order: Vec<usize>,
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vector,
+}
+
+impl BvhTree {
+    pub fn new(bounds: &[Aabb]) -> BvhTree {
+        let mut primitives: Vec<BuildPrimitive> = bounds.iter().enumerate()
+            .map(|(index, bounds)| BuildPrimitive { index, bounds: *bounds, centroid: bounds.centroid() })
+            .collect();
+
+        let mut tree = BvhTree {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            primitive_order: Vec::with_capacity(bounds.len()),
+        };
+        if !primitives.is_empty() {
+            tree.build(&mut primitives, 0);
+        }
+        tree
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        match self.nodes.first() {
+            Some(root) => root.bounds,
+            None => Aabb::new(Vector::new_empty(), Vector::new_empty())
+        }
+    }
+
+    /// Recursively builds the subtree for `primitives`, returning the index of its root node.
+    fn build(&mut self, primitives: &mut [BuildPrimitive], depth: usize) -> usize {
+        debug_assert!(depth < TRAVERSAL_STACK_SIZE - 1, "bvh is too deep to traverse");
+        let bounds = primitives.iter().fold(Aabb::empty(), |output, primitive| Aabb::surrounding_box(&output, &primitive.bounds));
+        let centroid_bounds = primitives.iter().fold(Aabb::empty(), |output, primitive| output.include(primitive.centroid));
+
+        let node_index = self.nodes.len();
+        self.nodes.push(BvhNode { bounds, offset: 0, count: 0, axis: 0 });
+
+        let split = if primitives.len() <= MAX_LEAF_SIZE {
+            None
+        } else if depth < SAH_MAX_DEPTH {
+            find_split(primitives, &bounds, &centroid_bounds)
+        } else {
+            median_split(primitives, &centroid_bounds)
+        };
+
+        match split {
+            Some((axis, mid)) => {
+                self.build(&mut primitives[..mid], depth + 1);
+                let right = self.build(&mut primitives[mid..], depth + 1);
+                self.nodes[node_index].offset = right;
+                self.nodes[node_index].axis = axis;
+            },
+            None => {
+                self.nodes[node_index].offset = self.primitive_order.len();
+                self.nodes[node_index].count = primitives.len();
+                self.primitive_order.extend(primitives.iter().map(|primitive| primitive.index));
+            }
+        }
+
+        node_index
+    }
+
+    /// Walks the tree front to back, calling `hit_primitive` for every primitive whose leaf the ray reaches.
+    ///
+    /// `hit_primitive` receives the primitive index and the current closest distance, and must fill the
+    /// record and return true when it finds a closer hit.
+    pub fn hit<'a, F>(&self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>, mut hit_primitive: F) -> bool
+    where F: FnMut(usize, &Ray, f64, f64, &mut HitRecord<'a>) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let inverse_direction = Vector::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let direction_is_negative = [inverse_direction.x < 0.0, inverse_direction.y < 0.0, inverse_direction.z < 0.0];
+
+        let mut hit_anything = false;
+        let mut closest = t_max;
+        // The build keeps the tree shallower than the stack, and each level leaves at most one node queued.
+        let mut stack = [0usize; TRAVERSAL_STACK_SIZE];
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node_index = stack[stack_len];
+            let node = &self.nodes[node_index];
+            if !node.bounds.hit_inverse(&ray.origin, &inverse_direction, t_min, closest) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for primitive in &self.primitive_order[node.offset..node.offset + node.count] {
+                    if hit_primitive(*primitive, ray, t_min, closest, hit_record) {
+                        hit_anything = true;
+                        closest = hit_record.t.unwrap();
+                    }
+                }
+            } else if direction_is_negative[node.axis] {
+                debug_assert!(stack_len + 2 <= TRAVERSAL_STACK_SIZE, "bvh traversal stack overflow");
+                // Push the far child first so the near one is visited first.
+                stack[stack_len] = node_index + 1;
+                stack[stack_len + 1] = node.offset;
+                stack_len += 2;
+            } else {
+                debug_assert!(stack_len + 2 <= TRAVERSAL_STACK_SIZE, "bvh traversal stack overflow");
+                stack[stack_len] = node.offset;
+                stack[stack_len + 1] = node_index + 1;
+                stack_len += 2;
+            }
+        }
+
+        hit_anything
+    }
+}
+
+/// Splits the primitives in two halves around the median centroid along the widest axis.
+fn median_split(primitives: &mut [BuildPrimitive], centroid_bounds: &Aabb) -> Option<(usize, usize)> {
+    let axis = largest_axis(&(centroid_bounds.maximum - centroid_bounds.minimum));
+    let mid = primitives.len() / 2;
+    primitives.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    Some((axis, mid))
+}
+
+fn largest_axis(extent: &Vector) -> usize {
+    if extent.x > extent.y && extent.x > extent.z {
+        0
+    } else if extent.y > extent.z {
+        1
+    } else {
+        2
+    }
+}
+
+/// Picks the cheapest bucket boundary according to the surface area heuristic.
+///
+/// Returns the split axis and the number of primitives that end up on the left side, or `None` when
+/// keeping all the primitives in a leaf is cheaper.
+fn find_split(primitives: &mut [BuildPrimitive], bounds: &Aabb, centroid_bounds: &Aabb) -> Option<(usize, usize)> {
+    let extent = centroid_bounds.maximum - centroid_bounds.minimum;
+    let axis = largest_axis(&extent);
+
+    // Every centroid sits on the same point, no split can separate them.
+    if extent[axis] <= 0.0 {
+        return None;
+    }
+
+    let bucket_of = |centroid: &Vector| -> usize {
+        let relative = (centroid[axis] - centroid_bounds.minimum[axis]) / extent[axis];
+        ((relative * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+    };
+
+    let mut counts = [0usize; SAH_BUCKETS];
+    let mut bucket_bounds = [Aabb::empty(); SAH_BUCKETS];
+    for primitive in primitives.iter() {
+        let bucket = bucket_of(&primitive.centroid);
+        counts[bucket] += 1;
+        bucket_bounds[bucket] = Aabb::surrounding_box(&bucket_bounds[bucket], &primitive.bounds);
+    }
+
+    let mut best_cost = f64::INFINITY;
+    let mut best_bucket = 0;
+    for split in 0..SAH_BUCKETS - 1 {
+        let (mut left_bounds, mut right_bounds) = (Aabb::empty(), Aabb::empty());
+        let (mut left_count, mut right_count) = (0, 0);
+        for bucket in 0..=split {
+            left_bounds = Aabb::surrounding_box(&left_bounds, &bucket_bounds[bucket]);
+            left_count += counts[bucket];
+        }
+        for bucket in split + 1..SAH_BUCKETS {
+            right_bounds = Aabb::surrounding_box(&right_bounds, &bucket_bounds[bucket]);
+            right_count += counts[bucket];
+        }
+
+        let cost = TRAVERSAL_COST + (left_count as f64 * left_bounds.surface_area()
+            + right_count as f64 * right_bounds.surface_area()) / bounds.surface_area();
+        if cost < best_cost {
+            best_cost = cost;
+            best_bucket = split;
+        }
+    }
+
+    if best_cost >= primitives.len() as f64 {
+        return None;
+    }
+
+    // Partition the primitives so the ones in the left buckets come first.
+    let mut mid = 0;
+    for i in 0..primitives.len() {
+        if bucket_of(&primitives[i].centroid) <= best_bucket {
+            primitives.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    if mid == 0 || mid == primitives.len() {
+        return None;
+    }
+
+    Some((axis, mid))
+}
+
+/// A collection of hittable objects accelerated by a bounding volume hierarchy.
+pub struct Bvh {
+    objects: Vec<Box<dyn Hittable>>,
+    tree: BvhTree,
+}
+
+impl Bvh {
+    pub fn new(list: HittableList) -> Bvh {
+        let bounds: Vec<Aabb> = list.objects.iter().map(|object| object.bounding_box()).collect();
+
+        Bvh {
+            objects: list.objects,
+            tree: BvhTree::new(&bounds),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        self.tree.hit(ray, t_min, t_max, hit_record, |index, ray, t_min, t_max, hit_record| {
+            let mut temp_record = HitRecord::new();
+            if self.objects[index].hit(ray, t_min, t_max, &mut temp_record) {
+                *hit_record = temp_record;
+                hit_record.object_id = Some(index);
+                return true;
+            }
+            false
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.tree.bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.objects.iter().flat_map(|object| object.lights()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::color::Color;
+    use crate::samplers::pcg::Pcg32;
+    use crate::shapes::{sphere::Sphere, material::Material};
+    use rand::Rng;
+
+    fn spheres(rng: &mut Pcg32) -> HittableList {
+        let mut list = HittableList::new();
+        for _ in 0..200 {
+            let center = Vector::random_with_constraint(-10.0, 10.0, rng);
+            let material = Material::new_lambertian(Color::random(rng));
+            list.add(Box::new(Sphere::new(center, rng.gen_range(0.1..1.0), material)));
+        }
+        list
+    }
+
+    #[test]
+    fn test_bvh_matches_linear_scan() {
+        let mut rng = Pcg32::new(42, 0);
+        let list = spheres(&mut rng);
+        let rays: Vec<Ray> = (0..500)
+            .map(|_| Ray::new(Vector::random_with_constraint(-15.0, 15.0, &mut rng), Vector::random_unit_vector(&mut rng)))
+            .collect();
+
+        let linear: Vec<Option<f64>> = rays.iter().map(|ray| {
+            let mut record = HitRecord::new();
+            list.hit(ray, 0.001, f64::INFINITY, &mut record).then(|| record.t.unwrap())
+        }).collect();
+
+        let bvh = Bvh::new(list);
+        assert_eq!(bvh.len(), 200);
+        for (ray, expected) in rays.iter().zip(linear) {
+            let mut record = HitRecord::new();
+            let t = bvh.hit(ray, 0.001, f64::INFINITY, &mut record).then(|| record.t.unwrap());
+            assert_eq!(t, expected);
+        }
+    }
+
+    #[test]
+    fn test_spread_out_primitives_stay_shallow() {
+        // Each box twice as far out as the last leaves the surface area heuristic peeling off one at a time.
+        let bounds: Vec<Aabb> = (0..300)
+            .map(|i| {
+                let x = 2.0f64.powi(i);
+                Aabb::new(Vector::new(x, -1.0, -1.0), Vector::new(x * 1.1, 1.0, 1.0))
+            })
+            .collect();
+        let tree = BvhTree::new(&bounds);
+
+        fn depth(tree: &BvhTree, node_index: usize) -> usize {
+            let node = &tree.nodes[node_index];
+            if node.count > 0 {
+                1
+            } else {
+                1 + depth(tree, node_index + 1).max(depth(tree, node.offset))
+            }
+        }
+        assert!(depth(&tree, 0) < TRAVERSAL_STACK_SIZE, "depth {}", depth(&tree, 0));
+
+        let ray = Ray::new(Vector::new(-1.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let mut visited = vec![false; bounds.len()];
+        tree.hit(&ray, 0.001, f64::INFINITY, &mut HitRecord::new(), |index, _, _, _, _| {
+            visited[index] = true;
+            false
+        });
+        assert!(visited.iter().all(|visited| *visited));
+    }
+}
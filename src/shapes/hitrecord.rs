@@ -1,23 +1,30 @@
 use crate::primitives::{ray::Ray, vector::Vector};
 use super::material::Material;
 
-#[derive(Clone, Copy, Debug)]
-pub struct HitRecord {
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HitRecord<'a> {
     pub point: Option<Vector>,
     pub normal: Option<Vector>,
     pub t: Option<f64>,
+    pub u: Option<f64>,
+    pub v: Option<f64>,
     pub front_face: Option<bool>,
-    pub material: Option<Material>
+    pub material: Option<&'a Material>,
+    /// Index of the top level object of the world that got hit.
+    pub object_id: Option<usize>,
 }
 
-impl HitRecord {
-    pub fn new() -> HitRecord {
+impl<'a> HitRecord<'a> {
+    pub fn new() -> HitRecord<'a> {
         HitRecord {
             point: None,
             normal: None,
             t: None,
+            u: None,
+            v: None,
             front_face: None,
             material: None,
+            object_id: None,
         }
     }
 
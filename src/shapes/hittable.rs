@@ -0,0 +1,16 @@
+use crate::primitives::{ray::Ray, aabb::Aabb};
+use super::{hitrecord::HitRecord, light::Light};
+
+/// Anything that a ray can be intersected with.
+///
+/// Implementors fill `hit_record` and return true when the ray hits them between `t_min` and `t_max`,
+/// the record borrows the material of the object that was hit.
+pub trait Hittable: Send + Sync {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool;
+    fn bounding_box(&self) -> Aabb;
+
+    /// The emissive surfaces of the object, for sampling lights directly.
+    fn lights(&self) -> Vec<Light> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,60 @@
+use crate::primitives::{ray::Ray, aabb::Aabb, vector::{Vector, Vec3}};
+use super::{hittable::Hittable, hitrecord::HitRecord, light::Light};
+
+/// A list of hittable objects, hitting the list returns the closest hit out of all its objects.
+#[derive(Default)]
+pub struct HittableList {
+    pub objects: Vec<Box<dyn Hittable>>,
+}
+
+impl HittableList {
+    pub fn new() -> HittableList {
+        HittableList {
+            objects: Vec::new()
+        }
+    }
+
+    pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.objects.push(object);
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        let mut temp_record = HitRecord::new();
+        let mut hit_anything = false;
+        let mut closest = t_max;
+
+        for (index, object) in self.objects.iter().enumerate() {
+            if object.hit(ray, t_min, closest, &mut temp_record) {
+                hit_anything = true;
+                closest = temp_record.t.unwrap();
+                *hit_record = temp_record;
+                hit_record.object_id = Some(index);
+            }
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self.objects.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.bounding_box(), |output, object| {
+                Aabb::surrounding_box(&output, &object.bounding_box())
+            }),
+            None => Aabb::new(Vector::new_empty(), Vector::new_empty())
+        }
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.objects.iter().flat_map(|object| object.lights()).collect()
+    }
+}
@@ -0,0 +1,172 @@
+use std::f64::consts::PI;
+
+use crate::primitives::{color::Color, vector::Vector};
+use super::{material::Material, moving_sphere::Motion};
+
+/// The surface of an emissive shape, kept separately from the world so points on it can be sampled directly.
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    Sphere { center: Vector, radius: f64, emission: Color },
+    MovingSphere { motion: Motion, radius: f64, emission: Color },
+    Triangle { vertices: [Vector; 3], emission: Color },
+}
+
+/// A point picked on one of the lights.
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    pub point: Vector,
+    pub normal: Vector,
+    pub emission: Color,
+    /// Probability density of picking the point, per unit of area.
+    pub pdf: f64,
+}
+
+impl Light {
+    /// The light covering a sphere, `None` when its material does not emit.
+    pub fn sphere(center: Vector, radius: f64, material: &Material) -> Option<Light> {
+        emission(material).map(|emission| Light::Sphere { center, radius, emission })
+    }
+
+    /// The light covering a sphere moving along `motion`, `None` when its material does not emit.
+    pub fn moving_sphere(motion: Motion, radius: f64, material: &Material) -> Option<Light> {
+        emission(material).map(|emission| Light::MovingSphere { motion, radius, emission })
+    }
+
+    /// The light covering a triangle, `None` when its material does not emit.
+    pub fn triangle(vertices: [Vector; 3], material: &Material) -> Option<Light> {
+        emission(material).map(|emission| Light::Triangle { vertices, emission })
+    }
+
+    pub fn area(&self) -> f64 {
+        match self {
+            Light::Sphere { radius, .. } | Light::MovingSphere { radius, .. } => 4.0 * PI * radius * radius,
+            Light::Triangle { vertices: [v0, v1, v2], .. } => 0.5 * (*v1 - *v0).cross(&(*v2 - *v0)).length(),
+        }
+    }
+
+    pub fn emission(&self) -> Color {
+        match self {
+            Light::Sphere { emission, .. } | Light::MovingSphere { emission, .. } | Light::Triangle { emission, .. } => *emission,
+        }
+    }
+
+    /// Total brightness given off by the light, used to pick bright lights more often.
+    pub fn power(&self) -> f64 {
+        self.emission().luminance() * self.area()
+    }
+
+    /// Maps a point of the unit square uniformly onto the surface as it is at `time`, returning the point and the
+    /// outward normal there.
+    pub fn sample_surface(&self, (u, v): (f64, f64), time: f64) -> (Vector, Vector) {
+        match self {
+            Light::Sphere { center, radius, .. } => {
+                let normal = Vector::unit_vector_from_sample((u, v));
+                (*center + *radius * normal, normal)
+            },
+            Light::MovingSphere { motion, radius, .. } => {
+                let normal = Vector::unit_vector_from_sample((u, v));
+                (motion.at(time) + *radius * normal, normal)
+            },
+            Light::Triangle { vertices: [v0, v1, v2], .. } => {
+                let root = u.sqrt();
+                let (b1, b2) = (root * (1.0 - v), root * v);
+                let point = (1.0 - b1 - b2) * *v0 + b1 * *v1 + b2 * *v2;
+                (point, (*v1 - *v0).cross(&(*v2 - *v0)).unit_vector())
+            },
+        }
+    }
+}
+
+/// Every light of the world, picked with a probability proportional to its power.
+#[derive(Clone, Debug, Default)]
+pub struct LightList {
+    lights: Vec<Light>,
+    /// Running sum of the light powers, the last entry is the total.
+    cdf: Vec<f64>,
+}
+
+impl LightList {
+    pub fn new(lights: Vec<Light>) -> LightList {
+        let lights: Vec<Light> = lights.into_iter().filter(|light| light.power() > 0.0).collect();
+        let cdf = lights.iter()
+            .scan(0.0, |total, light| {
+                *total += light.power();
+                Some(*total)
+            })
+            .collect();
+
+        LightList { lights, cdf }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    fn total_power(&self) -> f64 {
+        self.cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// Picks a light with `choice` and a point on it, where it is at `time`, with `position`.
+    pub fn sample(&self, choice: f64, position: (f64, f64), time: f64) -> Option<LightSample> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let target = choice * self.total_power();
+        let index = self.cdf.partition_point(|power| *power <= target).min(self.lights.len() - 1);
+        let light = &self.lights[index];
+        let (point, normal) = light.sample_surface(position, time);
+
+        Some(LightSample { point, normal, emission: light.emission(), pdf: self.pdf(light.emission()) })
+    }
+
+    /// Probability density, per unit of area, of `sample` picking a given point on a light that emits `emission`.
+    ///
+    /// Picking a light by power and then a point uniformly on it makes this the same everywhere on the light.
+    pub fn pdf(&self, emission: Color) -> f64 {
+        match self.total_power() {
+            total if total > 0.0 => emission.luminance() / total,
+            _ => 0.0,
+        }
+    }
+}
+
+fn emission(material: &Material) -> Option<Color> {
+    let emission = material.emitted();
+    (emission.max_component() > 0.0).then_some(emission)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::vector::Vec3;
+
+    #[test]
+    fn test_brighter_lights_are_picked_more_often() {
+        let dim = Material::new_diffuse_light(Color::new(1.0, 1.0, 1.0));
+        let bright = Material::new_diffuse_light(Color::new(3.0, 3.0, 3.0));
+        let triangle = |x: f64, material: &Material| Light::triangle([
+            Vector::new(x, 0.0, 0.0), Vector::new(x + 1.0, 0.0, 0.0), Vector::new(x, 1.0, 0.0)
+        ], material);
+        let lights = LightList::new(vec![
+            triangle(0.0, &dim).unwrap(),
+            triangle(10.0, &bright).unwrap(),
+        ]);
+        assert!(triangle(0.0, &Material::new_lambertian(Color::new_white())).is_none());
+
+        let count = 1000;
+        let picked_bright = (0..count)
+            .map(|i| lights.sample((i as f64 + 0.5) / count as f64, (0.5, 0.5), 0.0).unwrap())
+            .filter(|sample| sample.point.x > 5.0)
+            .count();
+        assert_eq!(picked_bright, 750);
+
+        // Density per area times the area of both lights adds up to one.
+        let total = lights.pdf(dim.emitted()) * 0.5 + lights.pdf(bright.emitted()) * 0.5;
+        assert!((total - 1.0).abs() < 0.0000001);
+    }
+}
@@ -1,34 +1,46 @@
+use std::{sync::Arc, f64::consts::PI};
+
 use num::traits::Pow;
 
 use crate::{
     primitives::{color::Color, ray::Ray, vector::Vector}, 
-    shapes::hitrecord::HitRecord
+    shapes::hitrecord::HitRecord,
+    textures::{texture::Texture, solid_color::SolidColor},
+    samplers::sampler::Sampler
 };
 
-use rand::Rng;
-
 #[derive(Clone, Copy, Debug)]
 pub enum MaterialType{
     Lambertian,
     Metal,
-    Dielectric
+    Dielectric,
+    DiffuseLight
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Material {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: f64,
     pub mat_type: MaterialType,
     pub index_of_refraction: f64,
+    pub emission: Color,
+    /// Identifies the material in the material id output, a hash of its name or 0 when it has none.
+    pub id: u32,
 }
 
 impl Material {
     pub fn new_lambertian(albedo: Color) -> Material {
+        Material::new_textured_lambertian(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn new_textured_lambertian(albedo: Arc<dyn Texture>) -> Material {
         Material {
             albedo,
             fuzz: 0.0,
             mat_type: MaterialType::Lambertian,
-            index_of_refraction: 0.0
+            index_of_refraction: 0.0,
+            emission: Color::new_black(),
+            id: 0
         }
     }
 
@@ -37,75 +49,128 @@ impl Material {
             fuzz = 1.0;
         }
         Material {
-            albedo,
+            albedo: Arc::new(SolidColor::new(albedo)),
             mat_type: MaterialType::Metal,
             fuzz,
-            index_of_refraction: 0.0
+            index_of_refraction: 0.0,
+            emission: Color::new_black(),
+            id: 0
         }
     }
 
     pub fn new_dielectric(albedo: Color, index_of_refraction: f64) -> Material {
         Material {
-            albedo,
+            albedo: Arc::new(SolidColor::new(albedo)),
             mat_type: MaterialType::Dielectric,
             fuzz: 0.0,
-            index_of_refraction
+            index_of_refraction,
+            emission: Color::new_black(),
+            id: 0
+        }
+    }
+
+    /// A material that emits light and does not scatter any, `emission` can go above 1 for bright lights.
+    pub fn new_diffuse_light(emission: Color) -> Material {
+        Material {
+            albedo: Arc::new(SolidColor::new(Color::new_black())),
+            mat_type: MaterialType::DiffuseLight,
+            fuzz: 0.0,
+            index_of_refraction: 0.0,
+            emission,
+            id: 0
         }
     }
 
-    pub fn scatter(
-        &self, ray_in: &Ray, record: &HitRecord, 
-        attenuation: &mut Color, scattered: &mut Ray
-    ) -> bool {
+    /// Replaces the albedo of the material with a texture, for metals and dielectrics this tints the reflection.
+    pub fn with_texture(mut self, albedo: Arc<dyn Texture>) -> Material {
+        self.albedo = albedo;
+        self
+    }
+
+    /// Names the material, which gives it a material id that stays the same between renders.
+    pub fn with_name(mut self, name: &str) -> Material {
+        self.id = name_id(name);
+        self
+    }
+
+    /// Color of the albedo texture at the hit.
+    pub fn albedo_at(&self, record: &HitRecord) -> Color {
+        self.albedo.value(record.u.unwrap_or(0.0), record.v.unwrap_or(0.0), &record.point.unwrap())
+    }
+
+    /// Light emitted by the material towards the viewer.
+    pub fn emitted(&self) -> Color {
+        self.emission
+    }
+
+    /// Whether the material scatters light arriving from every direction, which makes sampling lights directly worthwhile.
+    pub fn is_diffuse(&self) -> bool {
+        matches!(self.mat_type, MaterialType::Lambertian)
+    }
+
+    /// Picks a direction to continue a path that hit the material along `ray_in`, `None` when the path gets absorbed.
+    pub fn sample(&self, ray_in: &Ray, record: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        let scattered = match self.mat_type {
+            MaterialType::Lambertian => self.sample_lambertian(record, sampler),
+            MaterialType::Metal => self.sample_metal(ray_in, record, sampler),
+            MaterialType::Dielectric => self.sample_dielectric(ray_in, record, sampler),
+            MaterialType::DiffuseLight => None
+        };
+        // The path continues at the same moment, so it sees moving objects where the incoming ray did.
+        scattered.map(|scattered| ScatterSample { ray: scattered.ray.with_time(ray_in.time), ..scattered })
+    }
+
+    /// Light arriving along `direction` that gets scattered towards the viewer, the BSDF times the cosine term.
+    ///
+    /// Only diffuse materials have one, metals and dielectrics only scatter into the directions `sample` picks.
+    pub fn eval(&self, record: &HitRecord, direction: &Vector) -> Color {
         match self.mat_type {
-            MaterialType::Lambertian => {
-                return self.scatter_lambertian(ray_in, record, attenuation, scattered);
-            },
-            MaterialType::Metal => {
-                return self.scatter_metal(ray_in, record, attenuation, scattered);
-            },
-            MaterialType::Dielectric => {
-                return self.scatter_dielectric(ray_in, record, attenuation, scattered)
-            }
+            MaterialType::Lambertian => self.albedo_at(record) * self.pdf(record, direction),
+            _ => Color::new_black()
         }
     }
 
-    pub fn scatter_lambertian(
-        &self, _ray_in: &Ray, record: &HitRecord, 
-        attenuation: &mut Color, scattered: &mut Ray
-    ) -> bool{
-        let mut scatter_direction = record.normal.unwrap() + Vector::random_unit_vector();
-        if scatter_direction.near_zero() {
-            scatter_direction = record.normal.unwrap();
+    /// Probability density, per unit of solid angle, of `sample` picking `direction`.
+    pub fn pdf(&self, record: &HitRecord, direction: &Vector) -> f64 {
+        match self.mat_type {
+            MaterialType::Lambertian => record.normal.unwrap().dot(&direction.unit_vector()).max(0.0) / PI,
+            _ => 0.0
         }
+    }
 
-        *scattered = Ray::new(record.point.unwrap(), scatter_direction);
-        *attenuation = self.albedo;
-        return true;
+    /// Cosine weighted directions over the hemisphere, which cancels the cosine term against the density.
+    fn sample_lambertian(&self, record: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
+        let direction = Vector::cosine_direction_from_sample(&record.normal.unwrap(), sampler.get_2d());
+        let pdf = self.pdf(record, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterSample {
+            ray: Ray::new(record.point.unwrap(), direction),
+            weight: self.eval(record, &direction) * (1.0 / pdf),
+            pdf,
+            specular: false
+        })
     }
 
-    pub fn scatter_metal(
-        &self, ray_in: &Ray, record: &HitRecord, 
-        attenuation: &mut Color, scattered: &mut Ray
-    ) -> bool{
+    fn sample_metal(&self, ray_in: &Ray, record: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
         let reflected = ray_in.direction.unit_vector().reflect(&record.normal.unwrap());
-        *scattered = Ray::new(record.point.unwrap(), reflected + self.fuzz * Vector::random_unit_vector());
-        *attenuation = self.albedo;
-        
-        scattered.direction.dot(&record.normal.unwrap()) > 0.0
+        let direction = reflected + self.fuzz * Vector::unit_vector_from_sample(sampler.get_2d());
+        if direction.dot(&record.normal.unwrap()) <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterSample::specular(Ray::new(record.point.unwrap(), direction), self.albedo_at(record)))
     }
 
-    pub fn scatter_dielectric(
-        &self, ray_in: &Ray, record: &HitRecord, 
-        attenuation: &mut Color, scattered: &mut Ray
-    ) -> bool{
+    fn sample_dielectric(&self, ray_in: &Ray, record: &HitRecord, sampler: &mut dyn Sampler) -> Option<ScatterSample> {
         let reflectance = |cosine: f64, ref_idx: f64| -> f64{
             let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
             r0 = r0 * r0;
             r0 + (1.0 - r0) * (1.0 - cosine).pow(5)
         };
 
-        *attenuation = self.albedo;
         let refraction_ratio = match record.front_face.unwrap() {
             true => {1.0 / self.index_of_refraction},
             false => {self.index_of_refraction}
@@ -118,15 +183,36 @@ impl Material {
 
         let cannot_reflect = refraction_ratio * sin_theta > 1.0;
 
-        let direction;
-
-        if cannot_reflect || reflectance(cos_theta, refraction_ratio) > rand::thread_rng().gen() {
-            direction = unit_direction.reflect(&record.normal.unwrap());
+        let direction = if cannot_reflect || reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
+            unit_direction.reflect(&record.normal.unwrap())
         } else {
-            direction = unit_direction.refract(&record.normal.unwrap(), refraction_ratio);
-        }
+            unit_direction.refract(&record.normal.unwrap(), refraction_ratio)
+        };
+
+        Some(ScatterSample::specular(Ray::new(record.point.unwrap(), direction), self.albedo_at(record)))
+    }
+}
+
+/// A direction picked by `Material::sample`.
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterSample {
+    pub ray: Ray,
+    /// What the throughput of the path gets multiplied by, the BSDF times the cosine term over the density.
+    pub weight: Color,
+    /// Density of picking the direction per unit of solid angle, meaningless for specular samples.
+    pub pdf: f64,
+    /// Whether the direction came from a mirror-like lobe that `eval` and `pdf` know nothing about.
+    pub specular: bool,
+}
 
-        *scattered = Ray::new(record.point.unwrap(), direction);
-        true
+impl ScatterSample {
+    fn specular(ray: Ray, weight: Color) -> ScatterSample {
+        ScatterSample { ray, weight, pdf: 0.0, specular: true }
     }
-}
\ No newline at end of file
+}
+
+/// FNV-1a hash of the name, cut to 24 bits so the id survives being stored as a float. Never 0.
+fn name_id(name: &str) -> u32 {
+    let hash = name.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    (hash & 0xff_ffff).max(1)
+}
@@ -1,3 +1,10 @@
 pub mod sphere;
+pub mod moving_sphere;
 pub mod hitrecord;
-pub mod material;
\ No newline at end of file
+pub mod material;
+pub mod hittable;
+pub mod hittable_list;
+pub mod triangle;
+pub mod triangle_mesh;
+pub mod bvh;
+pub mod light;
@@ -0,0 +1,90 @@
+use super::{hitrecord::HitRecord, material::Material, hittable::Hittable, light::Light, sphere::hit_sphere};
+use crate::primitives::{vector::{Vector, Vec3}, ray::Ray, aabb::Aabb};
+
+/// A straight path from `start` at `start_time` to `end` at `end_time`, resting at either end outside of that.
+#[derive(Clone, Copy, Debug)]
+pub struct Motion {
+    pub start: Vector,
+    pub end: Vector,
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+impl Motion {
+    /// Where along the path something is at `time`.
+    pub fn at(&self, time: f64) -> Vector {
+        if time <= self.start_time {
+            return self.start;
+        }
+        if time >= self.end_time {
+            return self.end;
+        }
+        let t = (time - self.start_time) / (self.end_time - self.start_time);
+        self.start + t * (self.end - self.start)
+    }
+}
+
+/// A sphere whose center moves from `center0` at `time0` to `center1` at `time1`, so it blurs while the shutter is open.
+pub struct MovingSphere {
+    motion: Motion,
+    radius: f64,
+    material: Material
+}
+
+impl MovingSphere {
+    pub fn new(center0: Vector, center1: Vector, time0: f64, time1: f64, radius: f64, material: Material) -> MovingSphere {
+        MovingSphere {
+            motion: Motion { start: center0, end: center1, start_time: time0, end_time: time1 },
+            radius,
+            material
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vector {
+        self.motion.at(time)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        hit_sphere(self.center(ray.time), self.radius, &self.material, ray, t_min, t_max, hit_record)
+    }
+
+    /// Covers the sphere at both ends of its path, and so everywhere in between.
+    fn bounding_box(&self) -> Aabb {
+        let radius = Vector::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.motion.start - radius, self.motion.start + radius);
+        let end = Aabb::new(self.motion.end - radius, self.motion.end + radius);
+        Aabb::surrounding_box(&start, &end)
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        Light::moving_sphere(self.motion, self.radius, &self.material).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::color::Color;
+
+    #[test]
+    fn test_rays_see_the_sphere_where_it_is_at_their_time() {
+        let material = Material::new_lambertian(Color::new_white());
+        let sphere = MovingSphere::new(Vector::new(0.0, 0.0, -5.0), Vector::new(4.0, 0.0, -5.0), 0.0, 1.0, 1.0, material);
+        assert!((sphere.center(0.25).x - 1.0).abs() < 0.0000001);
+        assert!((sphere.center(2.0).x - 4.0).abs() < 0.0000001);
+
+        let towards_start = Ray::new(Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&towards_start, 0.001, f64::INFINITY, &mut HitRecord::new()));
+        assert!(!sphere.hit(&towards_start.with_time(1.0), 0.001, f64::INFINITY, &mut HitRecord::new()));
+
+        let mut record = HitRecord::new();
+        let towards_end = Ray::new(Vector::new(4.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0)).with_time(1.0);
+        assert!(sphere.hit(&towards_end, 0.001, f64::INFINITY, &mut record));
+        assert!((record.t.unwrap() - 4.0).abs() < 0.0000001);
+
+        let bounds = sphere.bounding_box();
+        assert_eq!((bounds.minimum.x, bounds.maximum.x), (-1.0, 5.0));
+    }
+}
@@ -1,5 +1,7 @@
-use super::{hitrecord::HitRecord, material::Material};
-use crate::primitives::{vector::Vector, ray::Ray};
+use std::f64::consts::PI;
+
+use super::{hitrecord::HitRecord, material::Material, hittable::Hittable, light::Light};
+use crate::primitives::{vector::{Vector, Vec3}, ray::Ray, aabb::Aabb};
 pub struct Sphere {
     radius: f64,
     center: Vector,
@@ -14,36 +16,67 @@ impl Sphere {
             material
         }
     }
+}
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, mut hit_record: &mut HitRecord) -> bool {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
+impl Hittable for Sphere {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max, hit_record)
+    }
 
-        let discriminant = half_b * half_b - a * c;
-        
-        if discriminant < 0.0 {
-            return false;
-        }
+    fn bounding_box(&self) -> Aabb {
+        let radius = Vector::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - radius, self.center + radius)
+    }
 
-        let sqrt_discriminant = discriminant.sqrt();
+    fn lights(&self) -> Vec<Light> {
+        Light::sphere(self.center, self.radius, &self.material).into_iter().collect()
+    }
+}
 
+/// Intersects a ray with the sphere of `radius` around `center`, filling `hit_record` like `Hittable::hit`.
+pub(super) fn hit_sphere<'a>(
+    center: Vector, radius: f64, material: &'a Material, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>
+) -> bool {
+    let oc = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.length_squared() - radius * radius;
+
+    let discriminant = half_b * half_b - a * c;
+    
+    if discriminant < 0.0 {
+        return false;
+    }
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrt_discriminant) / a;
+    let sqrt_discriminant = discriminant.sqrt();
+
+
+    // Find the nearest root that lies in the acceptable range.
+    let mut root = (-half_b - sqrt_discriminant) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrt_discriminant) / a;
         if root < t_min || t_max < root {
-            root = (-half_b + sqrt_discriminant) / a;
-            if root < t_min || t_max < root {
-                return false;
-            }
+            return false;
         }
-
-        hit_record.t = Some(root);
-        hit_record.point = Some(ray.at(root));
-        let outward_normal = (hit_record.point.unwrap() - self.center) / self.radius;
-        hit_record.set_face_normal(&ray, outward_normal);
-        hit_record.material = Some(self.material);
-        return true;
     }
-}
\ No newline at end of file
+
+    hit_record.t = Some(root);
+    hit_record.point = Some(ray.at(root));
+    let outward_normal = (hit_record.point.unwrap() - center) / radius;
+    hit_record.set_face_normal(ray, outward_normal);
+    let (u, v) = sphere_uv(&outward_normal);
+    hit_record.u = Some(u);
+    hit_record.v = Some(v);
+    hit_record.material = Some(material);
+    true
+}
+
+/// Maps a point on the unit sphere to uv coordinates.
+///
+/// `u` goes around the y axis starting from -x, `v` goes from the bottom pole to the top one.
+fn sphere_uv(point: &Vector) -> (f64, f64) {
+    let theta = (-point.y).acos();
+    let phi = (-point.z).atan2(point.x) + PI;
+
+    (phi / (2.0 * PI), theta / PI)
+}
@@ -0,0 +1,134 @@
+use super::{hitrecord::HitRecord, material::Material, hittable::Hittable, light::Light};
+use crate::primitives::{vector::{Vector, Vec3}, ray::Ray, aabb::Aabb};
+
+/// Determinants smaller than this are treated as the ray being parallel to the triangle.
+const EPSILON: f64 = 1e-9;
+
+pub struct Triangle {
+    vertices: [Vector; 3],
+    material: Material
+}
+
+impl Triangle {
+    pub fn new(v0: Vector, v1: Vector, v2: Vector, material: Material) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+            material
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        let [v0, v1, v2] = self.vertices;
+        let (t, b1, b2) = match intersect(ray, v0, v1, v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false
+        };
+
+        hit_record.t = Some(t);
+        hit_record.point = Some(ray.at(t));
+        hit_record.set_face_normal(ray, (v1 - v0).cross(&(v2 - v0)).unit_vector());
+        hit_record.u = Some(b1);
+        hit_record.v = Some(b2);
+        hit_record.material = Some(&self.material);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        bounding_box(self.vertices[0], self.vertices[1], self.vertices[2])
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        Light::triangle(self.vertices, &self.material).into_iter().collect()
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection.
+///
+/// Returns the ray parameter `t` along with the barycentric coordinates of `v1` and `v2` at the hit point.
+pub fn intersect(ray: &Ray, v0: Vector, v1: Vector, v2: Vector, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge_1 = v1 - v0;
+    let edge_2 = v2 - v0;
+
+    let p = ray.direction.cross(&edge_2);
+    let determinant = edge_1.dot(&p);
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin - v0;
+    let b1 = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let q = s.cross(&edge_1);
+    let b2 = ray.direction.dot(&q) * inverse_determinant;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge_2.dot(&q) * inverse_determinant;
+    if t < t_min || t_max < t {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Bounding box of a triangle, padded so that axis aligned triangles do not produce a flat box.
+pub fn bounding_box(v0: Vector, v1: Vector, v2: Vector) -> Aabb {
+    let padding = 1e-4;
+    let minimum = Vector::new(
+        v0.x.min(v1.x).min(v2.x) - padding,
+        v0.y.min(v1.y).min(v2.y) - padding,
+        v0.z.min(v1.z).min(v2.z) - padding
+    );
+    let maximum = Vector::new(
+        v0.x.max(v1.x).max(v2.x) + padding,
+        v0.y.max(v1.y).max(v2.y) + padding,
+        v0.z.max(v1.z).max(v2.z) + padding
+    );
+
+    Aabb::new(minimum, maximum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intersect_hits_inside_triangle() {
+        let ray = Ray::new(Vector::new(0.25, 0.25, 1.0), Vector::new(0.0, 0.0, -1.0));
+        let (t, b1, b2) = intersect(
+            &ray,
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0),
+            0.001,
+            f64::INFINITY
+        ).unwrap();
+
+        assert!((t - 1.0).abs() < 0.0000001);
+        assert!((b1 - 0.25).abs() < 0.0000001);
+        assert!((b2 - 0.25).abs() < 0.0000001);
+    }
+
+    #[test]
+    fn test_intersect_misses_outside_triangle_and_parallel() {
+        let v0 = Vector::new(0.0, 0.0, 0.0);
+        let v1 = Vector::new(1.0, 0.0, 0.0);
+        let v2 = Vector::new(0.0, 1.0, 0.0);
+
+        let outside = Ray::new(Vector::new(0.75, 0.75, 1.0), Vector::new(0.0, 0.0, -1.0));
+        assert!(intersect(&outside, v0, v1, v2, 0.001, f64::INFINITY).is_none());
+
+        let parallel = Ray::new(Vector::new(0.25, 0.25, 1.0), Vector::new(1.0, 0.0, 0.0));
+        assert!(intersect(&parallel, v0, v1, v2, 0.001, f64::INFINITY).is_none());
+
+        let behind = Ray::new(Vector::new(0.25, 0.25, -1.0), Vector::new(0.0, 0.0, -1.0));
+        assert!(intersect(&behind, v0, v1, v2, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+
+use super::{hitrecord::HitRecord, material::Material, hittable::Hittable, light::Light, triangle, bvh::BvhTree};
+use crate::primitives::{vector::Vector, ray::Ray, aabb::Aabb};
+
+/// Why the parts handed to `TriangleMesh` do not fit together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeshError {
+    /// A triangle refers to a vertex past the end of the positions.
+    IndexOutOfRange { triangle: usize, index: usize, vertices: usize },
+    /// Normals or uvs were given for a different number of vertices than there are positions.
+    AttributeCount { attribute: &'static str, expected: usize, got: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeshError::IndexOutOfRange { triangle, index, vertices } => write!(
+                f, "triangle {} uses vertex {}, but the mesh only has {} vertices", triangle, index, vertices
+            ),
+            MeshError::AttributeCount { attribute, expected, got } => write!(
+                f, "a mesh needs exactly one {} per vertex, expected {} but got {}", attribute, expected, got
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+/// A mesh of triangles sharing a single vertex buffer.
+///
+/// Every entry of `indices` describes one triangle, the normals and uvs (when present) are indexed
+/// the same way as the positions.
+pub struct TriangleMesh {
+    positions: Vec<Vector>,
+    normals: Option<Vec<Vector>>,
+    uvs: Option<Vec<(f64, f64)>>,
+    indices: Vec<[usize; 3]>,
+    material: Material,
+    tree: BvhTree
+}
+
+impl TriangleMesh {
+    /// Builds a mesh, failing when a triangle refers to a vertex that does not exist.
+    pub fn new(positions: Vec<Vector>, indices: Vec<[usize; 3]>, material: Material) -> Result<TriangleMesh, MeshError> {
+        for (triangle, corners) in indices.iter().enumerate() {
+            if let Some(index) = corners.iter().find(|index| **index >= positions.len()) {
+                return Err(MeshError::IndexOutOfRange { triangle, index: *index, vertices: positions.len() });
+            }
+        }
+
+        let bounds: Vec<Aabb> = indices.iter()
+            .map(|[i0, i1, i2]| triangle::bounding_box(positions[*i0], positions[*i1], positions[*i2]))
+            .collect();
+        let tree = BvhTree::new(&bounds);
+
+        Ok(TriangleMesh {
+            positions,
+            normals: None,
+            uvs: None,
+            indices,
+            material,
+            tree
+        })
+    }
+
+    /// Adds a shading normal per vertex, failing when there is not exactly one for every position.
+    pub fn with_normals(mut self, normals: Vec<Vector>) -> Result<TriangleMesh, MeshError> {
+        check_count("normal", self.positions.len(), normals.len())?;
+        self.normals = Some(normals);
+        Ok(self)
+    }
+
+    /// Adds texture coordinates per vertex, failing when there is not exactly one pair for every position.
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Result<TriangleMesh, MeshError> {
+        check_count("uv", self.positions.len(), uvs.len())?;
+        self.uvs = Some(uvs);
+        Ok(self)
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Intersects a single triangle of the mesh and fills the record with its interpolated attributes.
+    fn hit_triangle<'a>(&'a self, index: usize, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        let [i0, i1, i2] = self.indices[index];
+        let (v0, v1, v2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+
+        let (t, b1, b2) = match triangle::intersect(ray, v0, v1, v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false
+        };
+        let b0 = 1.0 - b1 - b2;
+
+        hit_record.t = Some(t);
+        hit_record.point = Some(ray.at(t));
+        hit_record.set_face_normal(ray, (v1 - v0).cross(&(v2 - v0)).unit_vector());
+
+        if let Some(normals) = &self.normals {
+            let mut shading_normal = (b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]).unit_vector();
+            // Keep the shading normal on the same side as the geometric one so rays do not leak through.
+            if shading_normal.dot(&hit_record.normal.unwrap()) < 0.0 {
+                shading_normal = -shading_normal;
+            }
+            hit_record.normal = Some(shading_normal);
+        }
+
+        let (u, v) = match &self.uvs {
+            Some(uvs) => (
+                b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+                b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1
+            ),
+            None => (b1, b2)
+        };
+        hit_record.u = Some(u);
+        hit_record.v = Some(v);
+        hit_record.material = Some(&self.material);
+        true
+    }
+}
+
+fn check_count(attribute: &'static str, expected: usize, got: usize) -> Result<(), MeshError> {
+    if expected != got {
+        return Err(MeshError::AttributeCount { attribute, expected, got });
+    }
+    Ok(())
+}
+
+impl Hittable for TriangleMesh {
+    fn hit<'a>(&'a self, ray: &Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord<'a>) -> bool {
+        self.tree.hit(ray, t_min, t_max, hit_record, |index, ray, t_min, t_max, hit_record| {
+            self.hit_triangle(index, ray, t_min, t_max, hit_record)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.tree.bounding_box()
+    }
+
+    fn lights(&self) -> Vec<Light> {
+        self.indices.iter()
+            .filter_map(|[i0, i1, i2]| Light::triangle([self.positions[*i0], self.positions[*i1], self.positions[*i2]], &self.material))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{color::Color, vector::Vec3};
+
+    #[test]
+    fn test_mismatched_parts_are_rejected() {
+        let material = Material::new_lambertian(Color::new_white());
+        let positions = vec![Vector::new(0.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0)];
+
+        let error = TriangleMesh::new(positions.clone(), vec![[0, 1, 2], [0, 2, 3]], material.clone()).err().unwrap();
+        assert_eq!(error, MeshError::IndexOutOfRange { triangle: 1, index: 3, vertices: 3 });
+        assert_eq!(error.to_string(), "triangle 1 uses vertex 3, but the mesh only has 3 vertices");
+
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2]], material).unwrap();
+        let error = mesh.with_uvs(vec![(0.0, 0.0)]).err().unwrap();
+        assert_eq!(error.to_string(), "a mesh needs exactly one uv per vertex, expected 3 but got 1");
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use crate::primitives::{color::Color, vector::Vector};
+use super::{texture::Texture, solid_color::SolidColor};
+
+/// A 3D checker pattern alternating between two textures, every cell is `scale` units wide.
+#[derive(Debug, Clone)]
+pub struct CheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    scale: f64
+}
+
+impl CheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f64) -> CheckerTexture {
+        CheckerTexture {
+            even,
+            odd,
+            scale
+        }
+    }
+
+    pub fn from_colors(even: Color, odd: Color, scale: f64) -> CheckerTexture {
+        CheckerTexture::new(Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)), scale)
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, point: &Vector) -> Color {
+        let cell = (point.x / self.scale).floor() + (point.y / self.scale).floor() + (point.z / self.scale).floor();
+        if cell.rem_euclid(2.0) == 0.0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
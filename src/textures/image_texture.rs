@@ -0,0 +1,70 @@
+use std::{fmt, path::Path};
+
+use ::image::error::{ImageError, LimitError, LimitErrorKind};
+
+use crate::primitives::{color::Color, vector::Vector};
+use crate::output::tone_map::srgb_decode;
+use super::texture::Texture;
+
+/// A texture read from an image file, mapped onto the surface through its uv coordinates.
+///
+/// The image is converted from sRGB to linear colors when loaded, and repeats outside of the [0, 1] uv range.
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>
+}
+
+impl ImageTexture {
+    pub fn new(width: u32, height: u32, pixels: Vec<Color>) -> ImageTexture {
+        assert!(width > 0 && height > 0, "an image texture needs at least one pixel");
+        assert_eq!(pixels.len(), width as usize * height as usize, "an image texture needs one color per pixel");
+        ImageTexture {
+            width,
+            height,
+            pixels
+        }
+    }
+
+    /// Loads an image file, failing on images without any pixels.
+    pub fn load(path: impl AsRef<Path>) -> Result<ImageTexture, ImageError> {
+        let image = ::image::open(path)?.to_rgb8();
+        if image.width() == 0 || image.height() == 0 {
+            return Err(ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)));
+        }
+        let pixels = image.pixels()
+            .map(|pixel| Color::new(
+                srgb_to_linear(pixel[0]),
+                srgb_to_linear(pixel[1]),
+                srgb_to_linear(pixel[2])
+            ))
+            .collect();
+
+        Ok(ImageTexture::new(image.width(), image.height(), pixels))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Vector) -> Color {
+        let u = u.rem_euclid(1.0);
+        // Image rows start from the top, while v goes up.
+        let v = 1.0 - v.rem_euclid(1.0);
+
+        let i = ((u * self.width as f64) as u32).min(self.width - 1);
+        let j = ((v * self.height as f64) as u32).min(self.height - 1);
+        self.pixels[(j * self.width + i) as usize]
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    srgb_decode(value as f64 / 255.0)
+}
@@ -0,0 +1,4 @@
+pub mod texture;
+pub mod solid_color;
+pub mod checker;
+pub mod image_texture;
@@ -0,0 +1,22 @@
+use crate::primitives::{color::Color, vector::Vector};
+use super::texture::Texture;
+
+/// The same color everywhere.
+#[derive(Debug, Clone, Copy)]
+pub struct SolidColor {
+    color: Color
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> SolidColor {
+        SolidColor {
+            color
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: &Vector) -> Color {
+        self.color
+    }
+}
@@ -0,0 +1,10 @@
+use std::fmt::Debug;
+
+use crate::primitives::{color::Color, vector::Vector};
+
+/// A color that varies over the surface of an object.
+///
+/// Textures are evaluated at the surface coordinates `u` and `v` of the hit, along with the hit point itself.
+pub trait Texture: Send + Sync + Debug {
+    fn value(&self, u: f64, v: f64, point: &Vector) -> Color;
+}